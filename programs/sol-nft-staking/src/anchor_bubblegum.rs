@@ -0,0 +1,181 @@
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::solana_program::keccak;
+use anchor_lang::solana_program::program::invoke_signed;
+
+declare_id!("BGUMAp9Gq7iTEuizy4pqaxsTyUCBK68MDfK752saRPUY");
+
+pub mod spl_account_compression {
+    use anchor_lang::prelude::*;
+
+    declare_id!("cmtDvXumGCrqC1Age74AVPhSRVXJMd8PJS91L8KbNCK");
+}
+
+pub mod spl_noop {
+    use anchor_lang::prelude::*;
+
+    declare_id!("noopb9bkMVfRPU8AsbpTUg8AQkHtKwMYZiFUjNRtMmV");
+}
+
+pub const ASSET_PREFIX: &[u8] = b"asset";
+
+// sighash of the bubblegum anchor instruction "global:transfer"
+const TRANSFER: [u8; 8] = [163, 52, 200, 231, 140, 3, 69, 186];
+
+#[derive(Clone)]
+pub struct Bubblegum;
+
+impl anchor_lang::Id for Bubblegum {
+    fn id() -> Pubkey {
+        ID
+    }
+}
+
+#[derive(Clone)]
+pub struct SplAccountCompression;
+
+impl anchor_lang::Id for SplAccountCompression {
+    fn id() -> Pubkey {
+        spl_account_compression::ID
+    }
+}
+
+#[derive(Clone)]
+pub struct SplNoop;
+
+impl anchor_lang::Id for SplNoop {
+    fn id() -> Pubkey {
+        spl_noop::ID
+    }
+}
+
+/// The asset id of the compressed NFT minted into a tree with the given nonce
+pub fn get_asset_id(merkle_tree: &Pubkey, nonce: u64) -> Pubkey {
+    Pubkey::find_program_address(
+        &[ASSET_PREFIX, merkle_tree.as_ref(), &nonce.to_le_bytes()],
+        &ID,
+    )
+    .0
+}
+
+/// The leaf of a compressed NFT as it is currently stored in its tree
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct LeafArgs {
+    pub root: [u8; 32],
+    pub data_hash: [u8; 32],
+    pub creator_hash: [u8; 32],
+    pub nonce: u64,
+    pub index: u32,
+}
+
+/// The metadata of a compressed NFT, hashed into the `data_hash` of its leaf
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct MetadataArgs {
+    pub name: String,
+    pub symbol: String,
+    pub uri: String,
+    pub seller_fee_basis_points: u16,
+    pub primary_sale_happened: bool,
+    pub is_mutable: bool,
+    pub edition_nonce: Option<u8>,
+    pub token_standard: Option<TokenStandard>,
+    pub collection: Option<Collection>,
+    pub uses: Option<Uses>,
+    pub token_program_version: TokenProgramVersion,
+    pub creators: Vec<Creator>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub enum TokenStandard {
+    NonFungible,
+    FungibleAsset,
+    Fungible,
+    NonFungibleEdition,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct Collection {
+    pub verified: bool,
+    pub key: Pubkey,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub enum UseMethod {
+    Burn,
+    Multiple,
+    Single,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct Uses {
+    pub use_method: UseMethod,
+    pub remaining: u64,
+    pub total: u64,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub enum TokenProgramVersion {
+    Original,
+    Token2022,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct Creator {
+    pub address: Pubkey,
+    pub verified: bool,
+    pub share: u8,
+}
+
+/// The `data_hash` Bubblegum stores in the leaf of a compressed NFT with this metadata
+pub fn hash_metadata(metadata: &MetadataArgs) -> anchor_lang::Result<[u8; 32]> {
+    let metadata_hash = keccak::hashv(&[&metadata.try_to_vec()?]);
+    Ok(keccak::hashv(&[
+        &metadata_hash.to_bytes(),
+        &metadata.seller_fee_basis_points.to_le_bytes(),
+    ])
+    .to_bytes())
+}
+
+/// Transfers a compressed NFT, verifying the leaf against the proof in the remaining accounts
+pub fn transfer<'a, 'b, 'c, 'info>(
+    ctx: CpiContext<'a, 'b, 'c, 'info, Transfer<'info>>,
+    leaf: &LeafArgs,
+) -> anchor_lang::Result<()> {
+    let mut accounts = vec![
+        AccountMeta::new_readonly(*ctx.accounts.tree_config.key, false),
+        AccountMeta::new_readonly(*ctx.accounts.leaf_owner.key, true),
+        AccountMeta::new_readonly(*ctx.accounts.leaf_delegate.key, false),
+        AccountMeta::new_readonly(*ctx.accounts.new_leaf_owner.key, false),
+        AccountMeta::new(*ctx.accounts.merkle_tree.key, false),
+        AccountMeta::new_readonly(*ctx.accounts.log_wrapper.key, false),
+        AccountMeta::new_readonly(*ctx.accounts.compression_program.key, false),
+        AccountMeta::new_readonly(*ctx.accounts.system_program.key, false),
+    ];
+    accounts.extend(
+        ctx.remaining_accounts
+            .iter()
+            .map(|proof_node| AccountMeta::new_readonly(*proof_node.key, false)),
+    );
+
+    let mut data = TRANSFER.to_vec();
+    data.extend(leaf.try_to_vec()?);
+
+    let ix = Instruction {
+        program_id: ID,
+        accounts,
+        data,
+    };
+    invoke_signed(&ix, &ctx.to_account_infos(), ctx.signer_seeds).map_err(Into::into)
+}
+
+#[derive(Accounts)]
+pub struct Transfer<'info> {
+    pub tree_config: AccountInfo<'info>,
+    pub leaf_owner: AccountInfo<'info>,
+    pub leaf_delegate: AccountInfo<'info>,
+    pub new_leaf_owner: AccountInfo<'info>,
+    pub merkle_tree: AccountInfo<'info>,
+    pub log_wrapper: AccountInfo<'info>,
+    pub compression_program: AccountInfo<'info>,
+    pub system_program: AccountInfo<'info>,
+}
@@ -1,7 +1,10 @@
 use std::ops::{Deref, DerefMut};
-use anchor_lang::prelude::{ProgramError, Pubkey};
-use 
-anchor_lang::solana_program::borsh::try_from_slice_unchecked;
+use anchor_lang::prelude::{
+    borsh, AccountInfo, Accounts, AnchorDeserialize, CpiContext, ProgramError, Pubkey,
+};
+use anchor_lang::ToAccountInfos;
+use anchor_lang::solana_program::instruction::{AccountMeta, Instruction};
+use anchor_lang::solana_program::program::invoke_signed;
 use metaplex_token_metadata::state::{ Metadata, MAX_METADATA_LEN};
 
 // use metaplex_token_metadata::state::{Key as MetaplexKey, Metadata, MAX_METADATA_LEN};
@@ -29,8 +32,20 @@ impl anchor_lang::Id for MetaplexTokenMetadata {
     }
 }
 
+/// The verified collection an NFT belongs to
+#[derive(AnchorDeserialize, Clone, Debug, PartialEq)]
+pub struct Collection {
+    pub verified: bool,
+    pub key: Pubkey,
+}
+
+/// Metadata with the fields that were appended after the metaplex_token_metadata crate was released
 #[derive(Clone)]
-pub struct MetadataAccount(Metadata);
+pub struct MetadataAccount {
+    metadata: Metadata,
+    /// set for items of both sized and unsized collections
+    pub collection: Option<Collection>,
+}
 
 impl MetadataAccount {
     pub const LEN: usize = MAX_METADATA_LEN;
@@ -42,9 +57,15 @@ impl anchor_lang::AccountDeserialize for MetadataAccount {
     // }
 
     fn try_deserialize_unchecked(buf: &mut &[u8]) -> anchor_lang::Result<Self> {
-        let metadata: Metadata = try_from_slice_unchecked(buf)
+        let metadata = Metadata::deserialize(buf)
             .map_err(|err| ProgramError::BorshIoError(err.to_string()))?;
-        Ok(MetadataAccount(metadata))
+        // metadata written before these fields existed is zero padded, which reads as `None`
+        let collection = <(Option<u8>, Option<Collection>)>::deserialize(buf)
+            .map_or(None, |(_token_standard, collection)| collection);
+        Ok(MetadataAccount {
+            metadata,
+            collection,
+        })
     }
 }
 
@@ -65,12 +86,215 @@ impl Deref for MetadataAccount {
     type Target = Metadata;
 
     fn deref(&self) -> &Self::Target {
-        &self.0
+        &self.metadata
     }
 }
 
 impl DerefMut for MetadataAccount {
     fn deref_mut(&mut self) -> &mut Self::Target {
-        &mut self.0
+        &mut self.metadata
+    }
+}
+
+// Token Metadata instructions that are newer than the metaplex_token_metadata crate
+const FREEZE_DELEGATED_ACCOUNT: u8 = 26;
+const THAW_DELEGATED_ACCOUNT: u8 = 27;
+const DELEGATE: u8 = 44;
+const REVOKE: u8 = 45;
+const LOCK: u8 = 46;
+const UNLOCK: u8 = 47;
+
+// variant of DelegateArgs and RevokeArgs for a staking delegate
+const STAKING_V1: u8 = 5;
+// variant of LockArgs and UnlockArgs
+const V1: u8 = 0;
+// an empty authorization_data
+const NO_AUTHORIZATION_DATA: u8 = 0;
+
+fn delegated_account_instruction<'info>(
+    discriminator: u8,
+    accounts: &DelegatedAccount<'info>,
+) -> Instruction {
+    Instruction {
+        program_id: ID,
+        accounts: vec![
+            AccountMeta::new_readonly(*accounts.delegate.key, true),
+            AccountMeta::new(*accounts.token_account.key, false),
+            AccountMeta::new_readonly(*accounts.edition.key, false),
+            AccountMeta::new_readonly(*accounts.mint.key, false),
+            AccountMeta::new_readonly(*accounts.token_program.key, false),
+        ],
+        data: vec![discriminator],
+    }
+}
+
+/// Freezes a token account through the master edition of its mint, signed by the token account delegate
+pub fn freeze_delegated_account<'a, 'b, 'c, 'info>(
+    ctx: CpiContext<'a, 'b, 'c, 'info, DelegatedAccount<'info>>,
+) -> anchor_lang::Result<()> {
+    let ix = delegated_account_instruction(FREEZE_DELEGATED_ACCOUNT, &ctx.accounts);
+    invoke_signed(
+        &ix,
+        &[
+            ctx.accounts.delegate.clone(),
+            ctx.accounts.token_account.clone(),
+            ctx.accounts.edition.clone(),
+            ctx.accounts.mint.clone(),
+            ctx.accounts.token_program.clone(),
+        ],
+        ctx.signer_seeds,
+    )
+    .map_err(Into::into)
+}
+
+/// Thaws a token account frozen with [`freeze_delegated_account`], signed by the token account delegate
+pub fn thaw_delegated_account<'a, 'b, 'c, 'info>(
+    ctx: CpiContext<'a, 'b, 'c, 'info, DelegatedAccount<'info>>,
+) -> anchor_lang::Result<()> {
+    let ix = delegated_account_instruction(THAW_DELEGATED_ACCOUNT, &ctx.accounts);
+    invoke_signed(
+        &ix,
+        &[
+            ctx.accounts.delegate.clone(),
+            ctx.accounts.token_account.clone(),
+            ctx.accounts.edition.clone(),
+            ctx.accounts.mint.clone(),
+            ctx.accounts.token_program.clone(),
+        ],
+        ctx.signer_seeds,
+    )
+    .map_err(Into::into)
+}
+
+#[derive(Accounts)]
+pub struct DelegatedAccount<'info> {
+    pub delegate: AccountInfo<'info>,
+    pub token_account: AccountInfo<'info>,
+    pub edition: AccountInfo<'info>,
+    pub mint: AccountInfo<'info>,
+    pub token_program: AccountInfo<'info>,
+}
+
+fn token_delegate_instruction(data: Vec<u8>, accounts: &TokenDelegate) -> Instruction {
+    Instruction {
+        program_id: ID,
+        accounts: vec![
+            // no delegate record, staking delegates are tracked by the token record
+            AccountMeta::new_readonly(ID, false),
+            AccountMeta::new_readonly(*accounts.delegate.key, false),
+            AccountMeta::new(*accounts.metadata.key, false),
+            AccountMeta::new_readonly(*accounts.master_edition.key, false),
+            AccountMeta::new(*accounts.token_record.key, false),
+            AccountMeta::new_readonly(*accounts.mint.key, false),
+            AccountMeta::new(*accounts.token.key, false),
+            AccountMeta::new_readonly(*accounts.authority.key, true),
+            AccountMeta::new(*accounts.payer.key, true),
+            AccountMeta::new_readonly(*accounts.system_program.key, false),
+            AccountMeta::new_readonly(*accounts.sysvar_instructions.key, false),
+            AccountMeta::new_readonly(*accounts.spl_token_program.key, false),
+            AccountMeta::new_readonly(*accounts.authorization_rules_program.key, false),
+            AccountMeta::new_readonly(*accounts.authorization_rules.key, false),
+        ],
+        data,
+    }
+}
+
+/// Approves a staking delegate for a programmable NFT, signed by its owner
+pub fn delegate_staking<'a, 'b, 'c, 'info>(
+    ctx: CpiContext<'a, 'b, 'c, 'info, TokenDelegate<'info>>,
+    amount: u64,
+) -> anchor_lang::Result<()> {
+    let mut data = vec![DELEGATE, STAKING_V1];
+    data.extend_from_slice(&amount.to_le_bytes());
+    data.push(NO_AUTHORIZATION_DATA);
+    let ix = token_delegate_instruction(data, &ctx.accounts);
+    let mut account_infos = ctx.accounts.to_account_infos();
+    account_infos.push(ctx.program.clone());
+    invoke_signed(&ix, &account_infos, ctx.signer_seeds).map_err(Into::into)
+}
+
+/// Revokes the staking delegate of a programmable NFT, signed by its owner
+pub fn revoke_staking<'a, 'b, 'c, 'info>(
+    ctx: CpiContext<'a, 'b, 'c, 'info, TokenDelegate<'info>>,
+) -> anchor_lang::Result<()> {
+    let ix = token_delegate_instruction(vec![REVOKE, STAKING_V1], &ctx.accounts);
+    let mut account_infos = ctx.accounts.to_account_infos();
+    account_infos.push(ctx.program.clone());
+    invoke_signed(&ix, &account_infos, ctx.signer_seeds).map_err(Into::into)
+}
+
+#[derive(Accounts)]
+pub struct TokenDelegate<'info> {
+    pub delegate: AccountInfo<'info>,
+    pub metadata: AccountInfo<'info>,
+    pub master_edition: AccountInfo<'info>,
+    pub token_record: AccountInfo<'info>,
+    pub mint: AccountInfo<'info>,
+    pub token: AccountInfo<'info>,
+    pub authority: AccountInfo<'info>,
+    pub payer: AccountInfo<'info>,
+    pub system_program: AccountInfo<'info>,
+    pub sysvar_instructions: AccountInfo<'info>,
+    pub spl_token_program: AccountInfo<'info>,
+    pub authorization_rules_program: AccountInfo<'info>,
+    pub authorization_rules: AccountInfo<'info>,
+}
+
+fn lock_instruction(discriminator: u8, accounts: &LockToken) -> Instruction {
+    Instruction {
+        program_id: ID,
+        accounts: vec![
+            AccountMeta::new_readonly(*accounts.authority.key, true),
+            AccountMeta::new_readonly(*accounts.token_owner.key, false),
+            AccountMeta::new(*accounts.token.key, false),
+            AccountMeta::new_readonly(*accounts.mint.key, false),
+            AccountMeta::new(*accounts.metadata.key, false),
+            AccountMeta::new_readonly(*accounts.edition.key, false),
+            AccountMeta::new(*accounts.token_record.key, false),
+            AccountMeta::new(*accounts.payer.key, true),
+            AccountMeta::new_readonly(*accounts.system_program.key, false),
+            AccountMeta::new_readonly(*accounts.sysvar_instructions.key, false),
+            AccountMeta::new_readonly(*accounts.spl_token_program.key, false),
+            AccountMeta::new_readonly(*accounts.authorization_rules_program.key, false),
+            AccountMeta::new_readonly(*accounts.authorization_rules.key, false),
+        ],
+        data: vec![discriminator, V1, NO_AUTHORIZATION_DATA],
     }
 }
+
+/// Locks a programmable NFT in its token account, signed by its staking delegate
+pub fn lock<'a, 'b, 'c, 'info>(
+    ctx: CpiContext<'a, 'b, 'c, 'info, LockToken<'info>>,
+) -> anchor_lang::Result<()> {
+    let ix = lock_instruction(LOCK, &ctx.accounts);
+    let mut account_infos = ctx.accounts.to_account_infos();
+    account_infos.push(ctx.program.clone());
+    invoke_signed(&ix, &account_infos, ctx.signer_seeds).map_err(Into::into)
+}
+
+/// Unlocks a programmable NFT locked with [`lock`], signed by its staking delegate
+pub fn unlock<'a, 'b, 'c, 'info>(
+    ctx: CpiContext<'a, 'b, 'c, 'info, LockToken<'info>>,
+) -> anchor_lang::Result<()> {
+    let ix = lock_instruction(UNLOCK, &ctx.accounts);
+    let mut account_infos = ctx.accounts.to_account_infos();
+    account_infos.push(ctx.program.clone());
+    invoke_signed(&ix, &account_infos, ctx.signer_seeds).map_err(Into::into)
+}
+
+#[derive(Accounts)]
+pub struct LockToken<'info> {
+    pub authority: AccountInfo<'info>,
+    pub token_owner: AccountInfo<'info>,
+    pub token: AccountInfo<'info>,
+    pub mint: AccountInfo<'info>,
+    pub metadata: AccountInfo<'info>,
+    pub edition: AccountInfo<'info>,
+    pub token_record: AccountInfo<'info>,
+    pub payer: AccountInfo<'info>,
+    pub system_program: AccountInfo<'info>,
+    pub sysvar_instructions: AccountInfo<'info>,
+    pub spl_token_program: AccountInfo<'info>,
+    pub authorization_rules_program: AccountInfo<'info>,
+    pub authorization_rules: AccountInfo<'info>,
+}
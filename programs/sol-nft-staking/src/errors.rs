@@ -64,4 +64,115 @@ pub enum StakingError {
 
     #[msg("The Metaplex Metadata account did not have the expected creators")]
     InvalidMetadataCreators,
+
+    #[msg("Too many boost tiers were provided for the rewarder")]
+    TooManyBoostTiers,
+
+    #[msg("The provided boost tiers contain a duplicate entry")]
+    DuplicateBoostTier,
+
+    #[msg("The locking period is not offered by the rewarder")]
+    InvalidLockingPeriod,
+
+    #[msg("The reward treasury does not hold enough tokens to pay the reward")]
+    InsufficientTreasuryBalance,
+
+    #[msg("The rewarder does not pay rewards from a treasury")]
+    RewardSourceNotTreasury,
+
+    #[msg("The withdrawal exceeds the rewards not owed to stakers")]
+    InsufficientUnallocatedRewards,
+
+    #[msg("The reward calculation overflowed")]
+    MathOverflow,
+
+    #[msg("The provided owner does not own the stake receipt")]
+    InvalidOwnerForStakeReceipt,
+
+    #[msg("The stake account already holds the maximum number of NFTs")]
+    TooManyNftsStaked,
+
+    #[msg("The NFT is not staked in the stake account")]
+    NftNotStaked,
+
+    #[msg("The asset id does not match the compressed NFT")]
+    InvalidAssetId,
+
+    #[msg("The account is not owned by the given token program")]
+    InvalidTokenProgram,
+
+    #[msg("The token account data could not be unpacked")]
+    InvalidTokenAccountData,
+
+    #[msg("The mint has a Token-2022 extension that would break staking")]
+    UnsupportedMintExtension,
+
+    #[msg("The stake mode of the rewarder does not support NFTs of this token program")]
+    UnsupportedTokenProgram,
+
+    #[msg("The NFT is not a verified member of the rewarder collection")]
+    UnverifiedCollectionMembership,
+
+    #[msg("The rarity weight of the NFT could not be proven")]
+    InvalidRarityProof,
+
+    #[msg("A trait boost has an empty or too long pattern")]
+    InvalidTraitBoost,
+
+    #[msg("The rewarder has no pending authority")]
+    NoPendingAuthority,
+
+    #[msg("The signer is not the pending authority of the rewarder")]
+    InvalidPendingAuthority,
+
+    #[msg("Staking is paused on the rewarder")]
+    StakingPaused,
+
+    #[msg("Claims are paused on the rewarder")]
+    ClaimsPaused,
+
+    #[msg("The signer is neither the authority nor the guardian of the rewarder")]
+    InvalidPauseAuthority,
+
+    #[msg("Only the rewarder authority can lift a pause")]
+    GuardianCannotUnpause,
+
+    #[msg("An early unstake slash cannot exceed 100%")]
+    InvalidEarlyUnstakePolicy,
+
+    #[msg("The unbonding period cannot be negative or longer than a year")]
+    InvalidUnbondingPeriod,
+
+    #[msg("Unstaking must be requested first, the rewarder has an unbonding period")]
+    UnbondingRequired,
+
+    #[msg("Unstaking this NFT has already been requested")]
+    UnstakeAlreadyRequested,
+
+    #[msg("Unstaking this NFT has not been requested")]
+    UnstakeNotRequested,
+
+    #[msg("The unbonding period of this NFT has not elapsed yet")]
+    UnbondingNotElapsed,
+
+    #[msg("The locking period of a staked NFT cannot be shortened")]
+    LockCannotBeShortened,
+
+    #[msg("The metadata of the cNFT is required when the rewarder enforces metadata")]
+    MissingCnftMetadata,
+
+    #[msg("The metadata does not match the data hash of the cNFT leaf")]
+    InvalidCnftMetadata,
+
+    #[msg("Too many staked mints have been removed from the whitelist")]
+    TooManyWhitelistRemovals,
+
+    #[msg("A stake receipt address is required for every mint removed from the whitelist")]
+    InvalidStakeReceiptAddress,
+
+    #[msg("The account is not a vault or stake account in the layout before stake receipts")]
+    InvalidLegacyAccount,
+
+    #[msg("NFTs staked before stake receipts must be unstaked before the accounts can be migrated")]
+    LegacyStakeNotEmpty,
 }
@@ -0,0 +1,31 @@
+use anchor_lang::prelude::*;
+
+use crate::state::PauseFlags;
+
+#[event]
+pub struct AuthorityProposed {
+    pub rewarder: Pubkey,
+    pub authority: Pubkey,
+    pub pending_authority: Pubkey,
+}
+
+#[event]
+pub struct AuthorityProposalCancelled {
+    pub rewarder: Pubkey,
+    pub authority: Pubkey,
+    pub pending_authority: Pubkey,
+}
+
+#[event]
+pub struct AuthorityAccepted {
+    pub rewarder: Pubkey,
+    pub previous_authority: Pubkey,
+    pub authority: Pubkey,
+}
+
+#[event]
+pub struct PauseUpdated {
+    pub rewarder: Pubkey,
+    pub signer: Pubkey,
+    pub paused: PauseFlags,
+}
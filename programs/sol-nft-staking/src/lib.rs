@@ -1,13 +1,24 @@
+// the error type of anchor is large, and every instruction returns it
+#![allow(clippy::result_large_err)]
+
 use anchor_lang::prelude::*;
+use anchor_lang::solana_program::sysvar;
+use anchor_lang::Discriminator;
 
+pub mod anchor_bubblegum;
 pub mod anchor_metaplex;
 pub mod errors;
+pub mod events;
+pub mod merkle;
 pub mod state;
+pub mod token_interface;
 
-use anchor_metaplex::MetadataAccount;
-use anchor_spl::associated_token::get_associated_token_address;
-use anchor_spl::token::{self, Mint, MintTo, SetAuthority, Token, TokenAccount};
+use anchor_bubblegum::{Bubblegum, LeafArgs, MetadataArgs, SplAccountCompression, SplNoop};
+use anchor_metaplex::{MetadataAccount, MetaplexTokenMetadata};
+use anchor_spl::associated_token::AssociatedToken;
+use anchor_spl::token::{self, Approve, Mint, Revoke, SetAuthority, Token, TokenAccount};
 use errors::*;
+use events::*;
 use spl_token::instruction::AuthorityType;
 use state::*;
 // use std::convert::TryInto;
@@ -16,6 +27,8 @@ use state::*;
 const REWARDER_PREFIX: &[u8] = b"rewarder";
 const ACCOUNT_PREFIX: &[u8] = b"stake_account";
 const VAULT_PREFIX: &[u8] = b"vault_account";
+const TREASURY_PREFIX: &[u8] = b"reward_treasury";
+const RECEIPT_PREFIX: &[u8] = b"stake_receipt";
 
 declare_id!("9pWhgVLHUWhKTYYDDrF1v5M5sNPnjfBBLqznGNXHNE7V");
 
@@ -33,6 +46,7 @@ pub mod sol_nft_staking {
         Ok(())
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn initialize_rewarder(
         ctx: Context<InitializeRewarder>,
         _rewarder_bump: u8,
@@ -42,7 +56,53 @@ pub mod sol_nft_staking {
         creators: Vec<CreatorStruct>,
         nft_update_authority: Pubkey,
         enforce_metadata: bool,
+        lock_tiers: Vec<LockTier>,
+        quantity_tiers: Vec<QuantityTier>,
+        reward_source: RewardSource,
+        stake_mode: StakeMode,
     ) -> Result<()> {
+        check_boost_tiers(&lock_tiers, &quantity_tiers)?;
+        let token_program = &ctx.accounts.token_program;
+        let reward_mint = token_interface::load_mint(&ctx.accounts.reward_mint, token_program.key)?;
+        check_reward_mint_extensions(&reward_mint.extensions, reward_source)?;
+        if reward_source == RewardSource::Mint
+            && !reward_mint
+                .mint_authority
+                .contains(&ctx.accounts.reward_authority.key())
+        {
+            return Err(StakingError::RewarderNotMintAuthority.into());
+        }
+
+        // create the treasury with the space the extensions of the reward mint require,
+        // mint backed rewarders never hold rewards
+        let reward_treasury_bump = *ctx.bumps.get("reward_treasury").unwrap();
+        if reward_source == RewardSource::Treasury {
+            let rewarder_key = ctx.accounts.rewarder.key();
+            let reward_treasury_seeds = &[
+                collection.as_bytes(),
+                &id().to_bytes(),
+                TREASURY_PREFIX,
+                &rewarder_key.to_bytes(),
+                &[reward_treasury_bump],
+            ];
+            let reward_treasury_signer = &[&reward_treasury_seeds[..]];
+            create_pda_account(
+                &ctx.accounts.authority,
+                &ctx.accounts.reward_treasury,
+                reward_mint.token_account_len(),
+                token_program.key,
+                &ctx.accounts.system_program,
+                &ctx.accounts.rent,
+                reward_treasury_signer,
+            )?;
+            let initialize_accounts = token_interface::InitializeAccount3 {
+                account: ctx.accounts.reward_treasury.to_account_info(),
+                mint: ctx.accounts.reward_mint.to_account_info(),
+            };
+            let initialize_ctx = CpiContext::new(token_program.to_account_info(), initialize_accounts);
+            token_interface::initialize_account3(initialize_ctx, &ctx.accounts.reward_authority.key())?;
+        }
+
         let rewarder = &mut ctx.accounts.rewarder;
 
         rewarder.authority = ctx.accounts.authority.key();
@@ -53,26 +113,265 @@ pub mod sol_nft_staking {
         rewarder.creators = creators;
         rewarder.collection = collection;
         rewarder.enforce_metadata = enforce_metadata;
+        rewarder.lock_tiers = lock_tiers;
+        rewarder.quantity_tiers = quantity_tiers;
+        rewarder.reward_source = reward_source;
+        rewarder.stake_mode = stake_mode;
+        rewarder.reward_treasury_bump = reward_treasury_bump;
         
         Ok(())
     }
 
-    pub fn update_reward_rate(ctx: Context<UpdateRewardRate>, new_rate: u64, _whitelist_addresses:Vec<Pubkey>) -> Result<()> {
+    pub fn update_boost_tiers(
+        ctx: Context<UpdateRewardRate>,
+        lock_tiers: Vec<LockTier>,
+        quantity_tiers: Vec<QuantityTier>,
+    ) -> Result<()> {
+        check_boost_tiers(&lock_tiers, &quantity_tiers)?;
+        let rewarder = &mut ctx.accounts.rewarder;
+        rewarder.lock_tiers = lock_tiers;
+        rewarder.quantity_tiers = quantity_tiers;
+        Ok(())
+    }
+
+    /// Proposes a new authority for the rewarder, which only takes over once it accepts
+    pub fn propose_authority(ctx: Context<UpdateRewardRate>, new_authority: Pubkey) -> Result<()> {
         let rewarder = &mut ctx.accounts.rewarder;
-        rewarder.reward_rate = new_rate;
-        for whitelist_address in _whitelist_addresses.iter() {
-            let found_match = rewarder
-                .whitelist_addresses
-                .iter()
-                .find(|known_whitelist_address| known_whitelist_address == &whitelist_address);
-            if found_match.is_none() {
-                rewarder.whitelist_addresses.push(whitelist_address.key());
-                rewarder.total_whitelist_address +=1;
+        rewarder.pending_authority = Some(new_authority);
+        emit!(AuthorityProposed {
+            rewarder: rewarder.key(),
+            authority: rewarder.authority,
+            pending_authority: new_authority,
+        });
+        Ok(())
+    }
+
+    pub fn cancel_authority_proposal(ctx: Context<UpdateRewardRate>) -> Result<()> {
+        let rewarder = &mut ctx.accounts.rewarder;
+        let pending_authority = rewarder
+            .pending_authority
+            .take()
+            .ok_or(StakingError::NoPendingAuthority)?;
+        emit!(AuthorityProposalCancelled {
+            rewarder: rewarder.key(),
+            authority: rewarder.authority,
+            pending_authority,
+        });
+        Ok(())
+    }
+
+    pub fn accept_authority(ctx: Context<AcceptAuthority>) -> Result<()> {
+        let rewarder = &mut ctx.accounts.rewarder;
+        let previous_authority = rewarder.authority;
+        rewarder.authority = ctx.accounts.pending_authority.key();
+        rewarder.pending_authority = None;
+        emit!(AuthorityAccepted {
+            rewarder: rewarder.key(),
+            previous_authority,
+            authority: rewarder.authority,
+        });
+        Ok(())
+    }
+
+    pub fn update_guardian(ctx: Context<UpdateRewardRate>, guardian: Option<Pubkey>) -> Result<()> {
+        let rewarder = &mut ctx.accounts.rewarder;
+        rewarder.guardian = guardian;
+        Ok(())
+    }
+
+    /// Halts staking, claims or accrual. The guardian can only pause more, lifting a pause
+    /// is left to the authority
+    pub fn set_paused(ctx: Context<SetPaused>, paused: PauseFlags) -> Result<()> {
+        let rewarder = &mut ctx.accounts.rewarder;
+        let signer = ctx.accounts.signer.key();
+        if signer != rewarder.authority && !rewarder.paused.is_subset_of(&paused) {
+            return Err(StakingError::GuardianCannotUnpause.into());
+        }
+
+        rewarder.set_paused(paused, ctx.accounts.clock.unix_timestamp);
+        emit!(PauseUpdated {
+            rewarder: rewarder.key(),
+            signer,
+            paused,
+        });
+        Ok(())
+    }
+
+    pub fn update_early_unstake_policy(
+        ctx: Context<UpdateRewardRate>,
+        early_unstake_policy: EarlyUnstakePolicy,
+    ) -> Result<()> {
+        if let EarlyUnstakePolicy::Slash { slash_bps } = early_unstake_policy {
+            if slash_bps as u64 > BPS_DENOMINATOR {
+                return Err(StakingError::InvalidEarlyUnstakePolicy.into());
             }
         }
+        let rewarder = &mut ctx.accounts.rewarder;
+        rewarder.early_unstake_policy = early_unstake_policy;
+        Ok(())
+    }
+
+    pub fn update_unbonding_period(ctx: Context<UpdateRewardRate>, unbonding_period: i64) -> Result<()> {
+        if !(0..=MAX_LOCKING_PERIOD).contains(&unbonding_period) {
+            return Err(StakingError::InvalidUnbondingPeriod.into());
+        }
+        let rewarder = &mut ctx.accounts.rewarder;
+        rewarder.unbonding_period = unbonding_period;
+        Ok(())
+    }
+
+    pub fn update_trait_boosts(
+        ctx: Context<UpdateRewardRate>,
+        trait_boosts: Vec<TraitBoost>,
+    ) -> Result<()> {
+        check_trait_boosts(&trait_boosts)?;
+        let rewarder = &mut ctx.accounts.rewarder;
+        rewarder.trait_boosts = trait_boosts;
+        Ok(())
+    }
+
+    pub fn update_reward_rate(ctx: Context<UpdateRewardRate>, new_rate: u64) -> Result<()> {
+        let rewarder = &mut ctx.accounts.rewarder;
+        rewarder.reward_rate = new_rate;
+        Ok(())
+    }
+
+    pub fn add_whitelist_mints(ctx: Context<UpdateRewardRate>, mints: Vec<Pubkey>) -> Result<()> {
+        let rewarder = &mut ctx.accounts.rewarder;
+        rewarder.add_whitelist_addresses(&mints, Clock::get()?.unix_timestamp);
+        Ok(())
+    }
+
+    /// Removed mints that are staked stop accruing rewards but can still be unstaked.
+    /// The remaining accounts are the stake receipt addresses of the mints, in the same order.
+    pub fn remove_whitelist_mints(ctx: Context<UpdateRewardRate>, mints: Vec<Pubkey>) -> Result<()> {
+        let rewarder = &mut ctx.accounts.rewarder;
+        let staked_mints = get_staked_mints(rewarder, &mints, ctx.remaining_accounts)?;
+        rewarder.remove_whitelist_addresses(&mints, &staked_mints, Clock::get()?.unix_timestamp)?;
+        Ok(())
+    }
+
+    /// The remaining accounts are the stake receipt addresses of every whitelisted mint, in the same order
+    pub fn clear_whitelist(ctx: Context<UpdateRewardRate>) -> Result<()> {
+        let rewarder = &mut ctx.accounts.rewarder;
+        let mints = rewarder.whitelist_addresses.clone();
+        let staked_mints = get_staked_mints(rewarder, &mints, ctx.remaining_accounts)?;
+        rewarder.remove_whitelist_addresses(&mints, &staked_mints, Clock::get()?.unix_timestamp)?;
+        Ok(())
+    }
+
+    pub fn update_emission_limits(
+        ctx: Context<UpdateRewardRate>,
+        max_total_emission: Option<u64>,
+        emission_end_ts: Option<i64>,
+    ) -> Result<()> {
+        let rewarder = &mut ctx.accounts.rewarder;
+        rewarder.advance_accrual_clock(Clock::get()?.unix_timestamp);
+        rewarder.max_total_emission = max_total_emission;
+        rewarder.emission_end_ts = emission_end_ts;
+        Ok(())
+    }
+
+    pub fn update_collection_mint(
+        ctx: Context<UpdateRewardRate>,
+        collection_mint: Option<Pubkey>,
+    ) -> Result<()> {
+        let rewarder = &mut ctx.accounts.rewarder;
+        rewarder.collection_mint = collection_mint;
+        Ok(())
+    }
+
+    pub fn update_whitelist_root(
+        ctx: Context<UpdateRewardRate>,
+        whitelist_root: Option<[u8; 32]>,
+    ) -> Result<()> {
+        let rewarder = &mut ctx.accounts.rewarder;
+        rewarder.whitelist_root = whitelist_root;
+        Ok(())
+    }
+
+    pub fn update_rarity_root(
+        ctx: Context<UpdateRewardRate>,
+        rarity_root: Option<[u8; 32]>,
+    ) -> Result<()> {
+        let rewarder = &mut ctx.accounts.rewarder;
+        rewarder.rarity_root = rarity_root;
         Ok(())
     }
 
+    pub fn fund_rewarder(ctx: Context<FundRewarder>, amount: u64) -> Result<()> {
+        let rewarder = &ctx.accounts.rewarder;
+        if rewarder.reward_source != RewardSource::Treasury {
+            return Err(StakingError::RewardSourceNotTreasury.into());
+        }
+
+        let token_program = &ctx.accounts.token_program;
+        let reward_mint = token_interface::load_mint(&ctx.accounts.reward_mint, token_program.key)?;
+        let authority_token_account =
+            token_interface::load_token_account(&ctx.accounts.authority_token_account, token_program.key)?;
+        if authority_token_account.mint != rewarder.reward_mint {
+            return Err(StakingError::InvalidRewardTokenAccount.into());
+        }
+
+        let transfer_accounts = token_interface::TransferChecked {
+            from: ctx.accounts.authority_token_account.to_account_info(),
+            mint: ctx.accounts.reward_mint.to_account_info(),
+            to: ctx.accounts.reward_treasury.to_account_info(),
+            authority: ctx.accounts.authority.to_account_info(),
+        };
+        let transfer_ctx = CpiContext::new(token_program.to_account_info(), transfer_accounts);
+        token_interface::transfer_checked(transfer_ctx, amount, reward_mint.decimals)
+    }
+
+    pub fn withdraw_unallocated_rewards(
+        ctx: Context<WithdrawUnallocatedRewards>,
+        amount: u64,
+    ) -> Result<()> {
+        let rewarder = &ctx.accounts.rewarder;
+        if rewarder.reward_source != RewardSource::Treasury {
+            return Err(StakingError::RewardSourceNotTreasury.into());
+        }
+        let token_program = &ctx.accounts.token_program;
+        let reward_mint = token_interface::load_mint(&ctx.accounts.reward_mint, token_program.key)?;
+        let reward_treasury =
+            token_interface::load_token_account(&ctx.accounts.reward_treasury, token_program.key)?;
+        let authority_token_account =
+            token_interface::load_token_account(&ctx.accounts.authority_token_account, token_program.key)?;
+        if authority_token_account.mint != rewarder.reward_mint {
+            return Err(StakingError::InvalidRewardTokenAccount.into());
+        }
+
+        // rewards accrued to stakers stay in the treasury, whether they were settled into stake accounts or not
+        let accrued_rewards = rewarder
+            .total_outstanding_rewards
+            .saturating_add(rewarder.unsettled_rewards(Clock::get()?.unix_timestamp));
+        let unallocated = reward_treasury.amount.saturating_sub(accrued_rewards);
+        if amount > unallocated {
+            return Err(StakingError::InsufficientUnallocatedRewards.into());
+        }
+
+        let reward_authority_seeds = &[
+            rewarder.collection.as_bytes(),
+            &id().to_bytes(),
+            REWARDER_PREFIX,
+            &rewarder.key().to_bytes(),
+            &[rewarder.reward_authority_bump],
+        ];
+        let reward_authority_signer = &[&reward_authority_seeds[..]];
+        let transfer_accounts = token_interface::TransferChecked {
+            from: ctx.accounts.reward_treasury.to_account_info(),
+            mint: ctx.accounts.reward_mint.to_account_info(),
+            to: ctx.accounts.authority_token_account.to_account_info(),
+            authority: ctx.accounts.reward_authority.to_account_info(),
+        };
+        let transfer_ctx = CpiContext::new_with_signer(
+            token_program.to_account_info(),
+            transfer_accounts,
+            reward_authority_signer,
+        );
+        token_interface::transfer_checked(transfer_ctx, amount, reward_mint.decimals)
+    }
+
     pub fn initialize_stake_account(
         ctx: Context<InitializeStakeAccount>,
         bump: u8,
@@ -89,7 +388,47 @@ pub mod sol_nft_staking {
         Ok(())
     }
 
-    pub fn stake_nft(ctx: Context<StakeNft>,locking_period:i64) -> Result<()> {
+    /// Converts the vault and stake accounts of the owner created before stake receipts, once no NFT is staked with them
+    pub fn migrate_stake_account(ctx: Context<MigrateStakeAccount>) -> Result<()> {
+        let owner = &ctx.accounts.owner;
+        let rewarder = &mut ctx.accounts.rewarder;
+        let stake_account = &ctx.accounts.stake_account;
+        let vault_account = &ctx.accounts.vault_account;
+
+        // the stake account kept its space, legacy accounts are told apart by the space of the vault
+        if vault_account.data_len() == VaultAccount::LEN {
+            return Err(StakingError::InvalidLegacyAccount.into());
+        }
+        let legacy_vault_account: LegacyVaultAccount =
+            load_legacy_account(vault_account, VaultAccount::discriminator())?;
+        let legacy_stake_account: LegacyNftStakeAccount =
+            load_legacy_account(stake_account, NftStakeAccount::discriminator())?;
+        if legacy_stake_account.owner != owner.key() || legacy_stake_account.rewarder != rewarder.key() {
+            return Err(StakingError::InvalidLegacyAccount.into());
+        }
+        let (new_vault_account, new_stake_account) =
+            migrate_legacy_accounts(rewarder, legacy_vault_account, legacy_stake_account)?;
+
+        // shrink the vault account to its new space and refund the rent it no longer needs
+        vault_account.realloc(VaultAccount::LEN, false)?;
+        let excess_lamports = vault_account
+            .lamports()
+            .saturating_sub(Rent::get()?.minimum_balance(VaultAccount::LEN));
+        **vault_account.try_borrow_mut_lamports()? -= excess_lamports;
+        **owner.try_borrow_mut_lamports()? += excess_lamports;
+
+        new_vault_account.try_serialize(&mut &mut vault_account.try_borrow_mut_data()?[..])?;
+        new_stake_account.try_serialize(&mut &mut stake_account.try_borrow_mut_data()?[..])?;
+
+        Ok(())
+    }
+
+    pub fn stake_nft(
+        ctx: Context<StakeNft>,
+        locking_period: i64,
+        whitelist_proof: Vec<[u8; 32]>,
+        rarity: Option<RarityWeight>,
+    ) -> Result<()> {
 
         let owner = &mut ctx.accounts.owner;
         let rewarder = &mut ctx.accounts.rewarder;
@@ -101,421 +440,2285 @@ pub mod sol_nft_staking {
         let vault_account = &mut ctx.accounts.vault_account;
         let clock = &ctx.accounts.clock;
 
-        if rewarder.enforce_metadata {
+        let nft_mint_state = token_interface::load_mint(nft_mint, token_program.key)?;
+        let nft_token_account_state = token_interface::load_token_account(nft_token_account, token_program.key)?;
+        check_nft_token_account(&nft_mint_state, &nft_mint.key(), &nft_token_account_state, &owner.key())?;
+        check_nft_mint_extensions(&nft_mint_state.extensions)?;
+        // token-2022 token accounts can't change owner and can't be frozen through Token Metadata
+        if *token_program.key != spl_token::ID && rewarder.stake_mode != StakeMode::Escrow {
+            return Err(StakingError::UnsupportedTokenProgram.into());
+        }
+
+        let metadata = if rewarder.enforce_metadata {
             let remaining = ctx.remaining_accounts;
             let metadata = get_metadata_account(remaining)?;
             check_metadata(&metadata, &nft_mint.key(), rewarder)?;
-        }
-        // Calculate and claim any pending rewards
-        let mut pending_reward = 0;
-        for  nft_staked in stake_account.nfts_staked.iter() {
-            let to_reward = calculate_reward(
-                rewarder.reward_rate,
-                nft_staked.num_staked,
-                nft_staked.locking_period,
-                stake_account.last_claimed,
-                clock.unix_timestamp,
-            );
-            pending_reward += to_reward
-        }
-
-        stake_account.claimed_reward += pending_reward;
-
-        stake_account.last_claimed = clock.unix_timestamp;
-
-        //make a nft item struct staked
-        let mut already_staked = false;
-        let nft_item = NftItem{
-            owner: *owner.to_account_info().key,
-            locking_period: locking_period,
-            start_staking: clock.unix_timestamp,
-            nft_mint: nft_mint.to_account_info().key(),
-            flag: true,
+            Some(metadata)
+        } else {
+            None
         };
 
         // checking if the nft is whitelisted
-        let mut whitelisted = false;
-        for whitelist_address in rewarder.whitelist_addresses.iter() {
-            if whitelist_address == &nft_item.nft_mint {
-                whitelisted = true;
-            }
-        }
-        if !whitelisted == true {
+        if !rewarder.is_whitelisted(&nft_mint.key(), &whitelist_proof) {
             return Err(StakingError::NFTWhitelisted.into());
         }
-       
-        // add the nft_info into vault account
-        for nft_item_staked in vault_account.nft_items_staked.iter_mut() {
-            if nft_item_staked.nft_mint == nft_mint.key() {
-                nft_item_staked.locking_period = locking_period;
-                nft_item_staked.start_staking = clock.unix_timestamp;
-                nft_item_staked.owner = *owner.to_account_info().key;
-                already_staked = true;
-            }
-        }
-        if already_staked !=true{
-            vault_account.nft_items_staked.push(nft_item);
-        }
-        vault_account.total_staked += 1;
 
-        // add the nft info into the NFTSTAKE
-        let mut able_added = false;
-        for nft_staked in stake_account.nfts_staked.iter_mut() {
-            if nft_staked.locking_period == locking_period {
-                nft_staked.num_staked += 1;
-                able_added = true;
-            } 
-        }
-        if !able_added {
-            let nft_staked = NftStaked{
-                locking_period: locking_period,
-                num_staked: 1
-            };
-            stake_account.nfts_staked.push(nft_staked);
-        } 
-        
-        
-        //transfer nft ownership to vault
-        let authority_accounts = SetAuthority {
-            current_authority: owner.to_account_info(),
-            account_or_mint: nft_token_account.to_account_info(),
-        };
-        let authority_ctx = CpiContext::new(token_program.to_account_info(), authority_accounts);
-        token::set_authority(
-            authority_ctx,
-            AuthorityType::AccountOwner,
-            Some(stake_account.key()),
+        let stake_receipt = &mut ctx.accounts.stake_receipt;
+        stake_receipt.bump = *ctx.bumps.get("stake_receipt").unwrap();
+        let data = metadata.as_ref().map(|metadata| &metadata.data);
+        stake_receipt.weight_bps = rewarder.weight_bps(&nft_mint.key(), rarity.as_ref(), data)?;
+        record_stake(
+            rewarder,
+            stake_account,
+            vault_account,
+            stake_receipt,
+            nft_mint.key(),
+            locking_period,
+            clock.unix_timestamp,
         )?;
 
+        match rewarder.stake_mode {
+            StakeMode::SetAuthority => {
+                //transfer nft ownership to vault
+                let authority_accounts = SetAuthority {
+                    current_authority: owner.to_account_info(),
+                    account_or_mint: nft_token_account.to_account_info(),
+                };
+                let authority_ctx = CpiContext::new(token_program.to_account_info(), authority_accounts);
+                token::set_authority(
+                    authority_ctx,
+                    AuthorityType::AccountOwner,
+                    Some(stake_account.key()),
+                )?;
+            }
+            StakeMode::Escrow => {
+                //move the nft into the escrow owned by the stake account
+                let nft_escrow = &ctx.accounts.nft_escrow;
+                let create_accounts = token_interface::CreateAssociatedTokenAccount {
+                    payer: owner.to_account_info(),
+                    associated_token: nft_escrow.to_account_info(),
+                    authority: stake_account.to_account_info(),
+                    mint: nft_mint.to_account_info(),
+                    system_program: ctx.accounts.system_program.to_account_info(),
+                    token_program: token_program.to_account_info(),
+                };
+                let create_ctx = CpiContext::new(
+                    ctx.accounts.associated_token_program.to_account_info(),
+                    create_accounts,
+                );
+                token_interface::create_associated_token_account_idempotent(create_ctx)?;
+
+                let transfer_accounts = token_interface::TransferChecked {
+                    from: nft_token_account.to_account_info(),
+                    mint: nft_mint.to_account_info(),
+                    to: nft_escrow.to_account_info(),
+                    authority: owner.to_account_info(),
+                };
+                let transfer_ctx = CpiContext::new(token_program.to_account_info(), transfer_accounts);
+                token_interface::transfer_checked(transfer_ctx, 1, nft_mint_state.decimals)?;
+            }
+            StakeMode::Freeze => {
+                //delegate the nft to the stake account and freeze it in the owner's wallet
+                let approve_accounts = Approve {
+                    to: nft_token_account.to_account_info(),
+                    delegate: stake_account.to_account_info(),
+                    authority: owner.to_account_info(),
+                };
+                let approve_ctx = CpiContext::new(token_program.to_account_info(), approve_accounts);
+                token::approve(approve_ctx, 1)?;
+
+                let stake_account_seeds = &[
+                    rewarder.collection.as_bytes(),
+                    &id().to_bytes(),
+                    ACCOUNT_PREFIX,
+                    &rewarder.key().to_bytes(),
+                    &owner.key().to_bytes(),
+                    &[stake_account.bump],
+                ];
+                let stake_account_signer = &[&stake_account_seeds[..]];
+                let freeze_accounts = anchor_metaplex::DelegatedAccount {
+                    delegate: stake_account.to_account_info(),
+                    token_account: nft_token_account.to_account_info(),
+                    edition: ctx.accounts.nft_edition.to_account_info(),
+                    mint: nft_mint.to_account_info(),
+                    token_program: token_program.to_account_info(),
+                };
+                let freeze_ctx = CpiContext::new_with_signer(
+                    ctx.accounts.token_metadata_program.to_account_info(),
+                    freeze_accounts,
+                    stake_account_signer,
+                );
+                anchor_metaplex::freeze_delegated_account(freeze_ctx)?;
+            }
+        }
+
         Ok(())
     }
 
-    pub fn unstake_nft(ctx: Context<UnstakeNft>,locking_period:i64) -> Result<()> {
-        let owner = &ctx.accounts.owner;
+    pub fn unstake_nft(ctx: Context<UnstakeNft>) -> Result<()> {
         let rewarder = &mut ctx.accounts.rewarder;
         let stake_account = &mut ctx.accounts.stake_account;
-        let nft_token_account = &ctx.accounts.nft_token_account;
-        let nft_mint = &ctx.accounts.nft_mint;
-        // let nft_vault = &ctx.accounts.nft_vault;
-
-        let token_program = &ctx.accounts.token_program;
+        let stake_receipt = &ctx.accounts.stake_receipt;
         let vault_account = &mut ctx.accounts.vault_account;
         let clock = &ctx.accounts.clock;
-        // check the locking period
-        let mut checked = false;
-        for nft_item in vault_account.nft_items_staked.iter_mut() {
-            if owner.to_account_info().key() == nft_item.owner {
-                if nft_mint.to_account_info().key() == nft_item.nft_mint {
-                    if locking_period == 0 {
-                        checked = true;
-                        nft_item.flag = false;
-                    } else if nft_item.start_staking + nft_item.locking_period <  clock.unix_timestamp {
-                        checked = true;
-                        nft_item.flag = false;
-                    }
-                }
-            }
-        }
-        if !checked == true {
-            return Err(StakingError::NFTAUnlocked.into());
-        }
 
-        // Calculate and claim any pending rewards
-        let mut total_staked = 0;
-        for nft_staked in stake_account.nfts_staked.iter() {
-            let to_reward = calculate_reward(
-                rewarder.reward_rate,
-                nft_staked.num_staked,
-                nft_staked.locking_period,
-                stake_account.last_claimed,
-                clock.unix_timestamp,
-            );
-            total_staked += to_reward
-        }
-        stake_account.claimed_reward += total_staked;
-        stake_account.last_claimed = clock.unix_timestamp;
+        let fee = release_stake(
+            rewarder,
+            stake_account,
+            vault_account,
+            stake_receipt,
+            clock.unix_timestamp,
+        )?;
+        pay_early_unstake_fee(
+            fee,
+            &ctx.accounts.rewarder,
+            &ctx.accounts.owner,
+            &ctx.accounts.reward_mint,
+            &ctx.accounts.reward_account,
+            &ctx.accounts.reward_treasury,
+            &ctx.accounts.reward_token_program,
+        )?;
 
-        //descrease the number of staked nfts by 1
-        for nft_staked in stake_account.nfts_staked.iter_mut() {
-            if nft_staked.locking_period == locking_period {
-                nft_staked.num_staked = nft_staked.num_staked.checked_sub(1).unwrap_or(0);
-                vault_account.total_staked = vault_account.total_staked.checked_sub(1).unwrap_or(0);
-            }
-        }
-        
-        let stake_account_seeds = &[
-            rewarder.collection.as_bytes(),
-            &id().to_bytes(),
-            ACCOUNT_PREFIX,
-            &rewarder.key().to_bytes(),
-            &owner.key().to_bytes(),
-            &[stake_account.bump],
-        ];
+        return_nft(ctx.accounts.return_nft_accounts())
+    }
 
-        let stake_account_signer = &[&stake_account_seeds[..]];
+    /// Starts the unbonding period of a staked NFT of any kind, it stops accruing rewards right away.
+    /// The early unstake policy applies when the NFT is still locked.
+    pub fn request_unstake(ctx: Context<RequestUnstake>) -> Result<()> {
+        let rewarder = &mut ctx.accounts.rewarder;
+        let stake_account = &mut ctx.accounts.stake_account;
+        let stake_receipt = &ctx.accounts.stake_receipt;
+        let clock = &ctx.accounts.clock;
 
-        //transfer nft to vault
-        let authority_accounts = SetAuthority {
-            current_authority: stake_account.to_account_info(),
-            account_or_mint: nft_token_account.to_account_info(),
-        };
-        let authority_ctx = CpiContext::new_with_signer(
-            token_program.to_account_info(),
-            authority_accounts,
-            stake_account_signer,
-        );
-        token::set_authority(
-            authority_ctx,
-            AuthorityType::AccountOwner,
-            Some(owner.key()),
+        let fee = request_release(rewarder, stake_account, stake_receipt, clock.unix_timestamp)?;
+        pay_early_unstake_fee(
+            fee,
+            &ctx.accounts.rewarder,
+            &ctx.accounts.owner,
+            &ctx.accounts.reward_mint,
+            &ctx.accounts.reward_account,
+            &ctx.accounts.reward_treasury,
+            &ctx.accounts.reward_token_program,
         )?;
-
         Ok(())
     }
 
-    pub fn claim(ctx: Context<Claim>) -> Result<()> {
-        let rewarder = &ctx.accounts.rewarder;
+    /// Returns an NFT staked with `stake_nft` once the unbonding period requested with
+    /// `request_unstake` has elapsed. pNFTs and cNFTs are returned by `unstake_pnft` and `unstake_cnft`.
+    pub fn complete_unstake(ctx: Context<UnstakeNft>) -> Result<()> {
+        let rewarder = &mut ctx.accounts.rewarder;
         let stake_account = &mut ctx.accounts.stake_account;
-        let reward_mint = &ctx.accounts.reward_mint;
-        let reward_autority = &ctx.accounts.reward_authority;
-        let reward_token_account = &ctx.accounts.reward_account;
-
-        let token_program = &ctx.accounts.token_program;
+        let stake_receipt = &ctx.accounts.stake_receipt;
+        let vault_account = &mut ctx.accounts.vault_account;
         let clock = &ctx.accounts.clock;
 
-        // Calculate and claim any pending rewards
-        let mut total_staked = 0;
-        for nft_staked in stake_account.nfts_staked.iter() {
-            let to_reward = calculate_reward(
-                rewarder.reward_rate,
-                nft_staked.num_staked,
-                nft_staked.locking_period,
-                stake_account.last_claimed,
-                clock.unix_timestamp,
-            );
-            total_staked += to_reward;
+        let requested = stake_account
+            .nfts_staked
+            .iter()
+            .any(|nft_staked| nft_staked.nft_mint == stake_receipt.nft_mint && nft_staked.unbonding_until.is_some());
+        if !requested {
+            return Err(StakingError::UnstakeNotRequested.into());
         }
-        stake_account.claimed_reward += total_staked;
-        
-        transfer_reward(
-            stake_account.claimed_reward,
+        let fee = release_stake(
             rewarder,
-            reward_mint,
-            reward_token_account,
-            reward_autority,
-            token_program,
+            stake_account,
+            vault_account,
+            stake_receipt,
+            clock.unix_timestamp,
+        )?;
+        pay_early_unstake_fee(
+            fee,
+            &ctx.accounts.rewarder,
+            &ctx.accounts.owner,
+            &ctx.accounts.reward_mint,
+            &ctx.accounts.reward_account,
+            &ctx.accounts.reward_treasury,
+            &ctx.accounts.reward_token_program,
         )?;
-        stake_account.last_claimed = clock.unix_timestamp;
-        stake_account.claimed_reward = 0;
 
-        Ok(())
+        return_nft(ctx.accounts.return_nft_accounts())
     }
-    pub fn check_balance(ctx: Context<Claim>) -> Result<()> {
-        let rewarder = &ctx.accounts.rewarder;
+
+    /// Moves a staked NFT of any kind to a lock tier at least as long as its current one. Rewards up to
+    /// now are settled at the old multiplier and the new locking period starts now.
+    pub fn extend_lock(ctx: Context<ExtendLock>, locking_period: i64) -> Result<()> {
+        let rewarder = &mut ctx.accounts.rewarder;
         let stake_account = &mut ctx.accounts.stake_account;
+        let stake_receipt = &mut ctx.accounts.stake_receipt;
         let clock = &ctx.accounts.clock;
 
-        // Calculate and claim any pending rewards
-        let mut total_staked = 0;
-        for nft_staked in stake_account.nfts_staked.iter() {
-            let to_reward = calculate_reward(
-                rewarder.reward_rate,
-                nft_staked.num_staked,
-                nft_staked.locking_period,
-                stake_account.last_claimed,
-                clock.unix_timestamp,
-            );
-            total_staked += to_reward;
-        }
-        stake_account.claimed_reward += total_staked;
-        stake_account.last_claimed = clock.unix_timestamp;
-
+        extend_stake(
+            rewarder,
+            stake_account,
+            stake_receipt,
+            locking_period,
+            clock.unix_timestamp,
+        )?;
         Ok(())
     }
-}
 
+    /// Returns the NFT to its owner without touching any reward accounts, in exchange the owner
+    /// forfeits every reward that has not been claimed yet. The lock and the unbonding period still apply.
+    pub fn emergency_unstake(ctx: Context<EmergencyUnstake>) -> Result<()> {
+        let rewarder = &mut ctx.accounts.rewarder;
+        let stake_account = &mut ctx.accounts.stake_account;
+        let stake_receipt = &ctx.accounts.stake_receipt;
+        let vault_account = &mut ctx.accounts.vault_account;
+        let clock = &ctx.accounts.clock;
 
-pub fn calculate_reward(
-    reward_rate: u64,
-    num_staked: u16,
-    locking_period: i64,
-    last_claimed: i64,
-    current_time: i64,
-) -> u64 {
-    if num_staked == 0 {
-        return 0;
-    }
-
-    let elapsed_time = current_time - last_claimed;
+        forfeit_stake(
+            rewarder,
+            stake_account,
+            vault_account,
+            stake_receipt,
+            clock.unix_timestamp,
+        )?;
 
-    if elapsed_time <= 0 {
-        return 0;
+        return_nft(ctx.accounts.return_nft_accounts())
     }
 
-    let mut default_reward = reward_rate * elapsed_time as u64 * num_staked as u64 / (24 * 3600);
-    
+    pub fn stake_pnft(
+        ctx: Context<StakePnft>,
+        locking_period: i64,
+        whitelist_proof: Vec<[u8; 32]>,
+        rarity: Option<RarityWeight>,
+    ) -> Result<()> {
+        let owner = &ctx.accounts.owner;
+        let rewarder = &mut ctx.accounts.rewarder;
+        let stake_account = &mut ctx.accounts.stake_account;
+        let nft_mint = &ctx.accounts.nft_mint;
+        let vault_account = &mut ctx.accounts.vault_account;
+        let clock = &ctx.accounts.clock;
 
-    if locking_period == 7 * 3600 *24 {
-        default_reward = default_reward * 125 / 100;
-    } else if locking_period == 30 * 3600 * 24 {
-        default_reward = default_reward * 150 / 100;
-    } else if locking_period == 60 * 3600 * 24{
-        default_reward = default_reward * 175 / 100;
-    } else if locking_period == 90 * 3600 *24 {
-        default_reward = default_reward * 2;
-    }
-    
-    if num_staked < 5 {
-        default_reward = default_reward;
-    } else if num_staked < 10 {
-        default_reward = default_reward * 125 / 100;
-    } else if num_staked < 15 {
-        default_reward = default_reward * 150 / 100;
-    }else if num_staked < 20 {
-        default_reward = default_reward * 175 / 100;
-    } else  {
-        default_reward = default_reward * 2;
-    }
+        let metadata = if rewarder.enforce_metadata {
+            let metadata = load_metadata_account(&ctx.accounts.nft_metadata)?;
+            check_metadata(&metadata, &nft_mint.key(), rewarder)?;
+            Some(metadata)
+        } else {
+            None
+        };
 
-    default_reward
+        // checking if the nft is whitelisted
+        if !rewarder.is_whitelisted(&nft_mint.key(), &whitelist_proof) {
+            return Err(StakingError::NFTWhitelisted.into());
+        }
 
-}
+        let stake_receipt = &mut ctx.accounts.stake_receipt;
+        stake_receipt.bump = *ctx.bumps.get("stake_receipt").unwrap();
+        let data = metadata.as_ref().map(|metadata| &metadata.data);
+        stake_receipt.weight_bps = rewarder.weight_bps(&nft_mint.key(), rarity.as_ref(), data)?;
+        record_stake(
+            rewarder,
+            stake_account,
+            vault_account,
+            stake_receipt,
+            nft_mint.key(),
+            locking_period,
+            clock.unix_timestamp,
+        )?;
 
-pub fn transfer_reward<'info>(
+        //make the stake account the staking delegate of the pnft
+        let delegate_accounts = anchor_metaplex::TokenDelegate {
+            delegate: stake_account.to_account_info(),
+            metadata: ctx.accounts.nft_metadata.to_account_info(),
+            master_edition: ctx.accounts.nft_edition.to_account_info(),
+            token_record: ctx.accounts.nft_token_record.to_account_info(),
+            mint: nft_mint.to_account_info(),
+            token: ctx.accounts.nft_token_account.to_account_info(),
+            authority: owner.to_account_info(),
+            payer: owner.to_account_info(),
+            system_program: ctx.accounts.system_program.to_account_info(),
+            sysvar_instructions: ctx.accounts.sysvar_instructions.to_account_info(),
+            spl_token_program: ctx.accounts.token_program.to_account_info(),
+            authorization_rules_program: ctx.accounts.authorization_rules_program.to_account_info(),
+            authorization_rules: ctx.accounts.authorization_rules.to_account_info(),
+        };
+        let delegate_ctx = CpiContext::new(
+            ctx.accounts.token_metadata_program.to_account_info(),
+            delegate_accounts,
+        );
+        anchor_metaplex::delegate_staking(delegate_ctx, 1)?;
+
+        //lock the pnft in the owner's wallet
+        let stake_account_seeds = &[
+            rewarder.collection.as_bytes(),
+            &id().to_bytes(),
+            ACCOUNT_PREFIX,
+            &rewarder.key().to_bytes(),
+            &owner.key().to_bytes(),
+            &[stake_account.bump],
+        ];
+        let stake_account_signer = &[&stake_account_seeds[..]];
+        let lock_accounts = anchor_metaplex::LockToken {
+            authority: stake_account.to_account_info(),
+            token_owner: owner.to_account_info(),
+            token: ctx.accounts.nft_token_account.to_account_info(),
+            mint: nft_mint.to_account_info(),
+            metadata: ctx.accounts.nft_metadata.to_account_info(),
+            edition: ctx.accounts.nft_edition.to_account_info(),
+            token_record: ctx.accounts.nft_token_record.to_account_info(),
+            payer: owner.to_account_info(),
+            system_program: ctx.accounts.system_program.to_account_info(),
+            sysvar_instructions: ctx.accounts.sysvar_instructions.to_account_info(),
+            spl_token_program: ctx.accounts.token_program.to_account_info(),
+            authorization_rules_program: ctx.accounts.authorization_rules_program.to_account_info(),
+            authorization_rules: ctx.accounts.authorization_rules.to_account_info(),
+        };
+        let lock_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_metadata_program.to_account_info(),
+            lock_accounts,
+            stake_account_signer,
+        );
+        anchor_metaplex::lock(lock_ctx)?;
+
+        Ok(())
+    }
+
+    pub fn unstake_pnft(ctx: Context<UnstakePnft>) -> Result<()> {
+        let owner = &ctx.accounts.owner;
+        let rewarder = &mut ctx.accounts.rewarder;
+        let stake_account = &mut ctx.accounts.stake_account;
+        let vault_account = &mut ctx.accounts.vault_account;
+        let clock = &ctx.accounts.clock;
+
+        let fee = release_stake(
+            rewarder,
+            stake_account,
+            vault_account,
+            &ctx.accounts.stake_receipt,
+            clock.unix_timestamp,
+        )?;
+        pay_early_unstake_fee(
+            fee,
+            rewarder,
+            owner,
+            &ctx.accounts.reward_mint,
+            &ctx.accounts.reward_account,
+            &ctx.accounts.reward_treasury,
+            &ctx.accounts.reward_token_program,
+        )?;
+
+        return_pnft(ctx.accounts.return_pnft_accounts())
+    }
+
+    /// Staking a cNFT is custodial, the stake account owns its leaf until it is unstaked. Bubblegum
+    /// cannot freeze a leaf, and a leaf delegate would be cleared by any transfer of the owner.
+    pub fn stake_cnft<'info>(
+        ctx: Context<'_, '_, '_, 'info, StakeCnft<'info>>,
+        locking_period: i64,
+        asset_id: Pubkey,
+        leaf: LeafArgs,
+        whitelist_proof: Vec<[u8; 32]>,
+        rarity: Option<RarityWeight>,
+        metadata: Option<MetadataArgs>,
+    ) -> Result<()> {
+        let owner = &ctx.accounts.owner;
+        let rewarder = &mut ctx.accounts.rewarder;
+        let stake_account = &mut ctx.accounts.stake_account;
+        let vault_account = &mut ctx.accounts.vault_account;
+        let clock = &ctx.accounts.clock;
+
+        if asset_id != anchor_bubblegum::get_asset_id(&ctx.accounts.merkle_tree.key(), leaf.nonce) {
+            return Err(StakingError::InvalidAssetId.into());
+        }
+
+        if rewarder.enforce_metadata {
+            let metadata = metadata.as_ref().ok_or(StakingError::MissingCnftMetadata)?;
+            check_cnft_metadata(metadata, &leaf, rewarder)?;
+        }
+
+        // checking if the nft is whitelisted
+        if !rewarder.is_whitelisted(&asset_id, &whitelist_proof) {
+            return Err(StakingError::NFTWhitelisted.into());
+        }
+
+        let stake_receipt = &mut ctx.accounts.stake_receipt;
+        stake_receipt.bump = *ctx.bumps.get("stake_receipt").unwrap();
+        // the metadata of a cNFT is not available on chain, so trait boosts do not apply
+        stake_receipt.weight_bps = rewarder.weight_bps(&asset_id, rarity.as_ref(), None)?;
+        record_stake(
+            rewarder,
+            stake_account,
+            vault_account,
+            stake_receipt,
+            asset_id,
+            locking_period,
+            clock.unix_timestamp,
+        )?;
+
+        //transfer the leaf to the stake account, bubblegum verifies it against the proof
+        let transfer_accounts = anchor_bubblegum::Transfer {
+            tree_config: ctx.accounts.tree_config.to_account_info(),
+            leaf_owner: owner.to_account_info(),
+            leaf_delegate: ctx.accounts.leaf_delegate.to_account_info(),
+            new_leaf_owner: stake_account.to_account_info(),
+            merkle_tree: ctx.accounts.merkle_tree.to_account_info(),
+            log_wrapper: ctx.accounts.log_wrapper.to_account_info(),
+            compression_program: ctx.accounts.compression_program.to_account_info(),
+            system_program: ctx.accounts.system_program.to_account_info(),
+        };
+        let transfer_ctx = CpiContext::new(
+            ctx.accounts.bubblegum_program.to_account_info(),
+            transfer_accounts,
+        )
+        .with_remaining_accounts(ctx.remaining_accounts.to_vec());
+        anchor_bubblegum::transfer(transfer_ctx, &leaf)?;
+
+        Ok(())
+    }
+
+    pub fn unstake_cnft<'info>(
+        ctx: Context<'_, '_, '_, 'info, UnstakeCnft<'info>>,
+        asset_id: Pubkey,
+        leaf: LeafArgs,
+    ) -> Result<()> {
+        let owner = &ctx.accounts.owner;
+        let rewarder = &mut ctx.accounts.rewarder;
+        let stake_account = &mut ctx.accounts.stake_account;
+        let vault_account = &mut ctx.accounts.vault_account;
+        let clock = &ctx.accounts.clock;
+
+        if asset_id != anchor_bubblegum::get_asset_id(&ctx.accounts.merkle_tree.key(), leaf.nonce) {
+            return Err(StakingError::InvalidAssetId.into());
+        }
+
+        let fee = release_stake(
+            rewarder,
+            stake_account,
+            vault_account,
+            &ctx.accounts.stake_receipt,
+            clock.unix_timestamp,
+        )?;
+        pay_early_unstake_fee(
+            fee,
+            rewarder,
+            owner,
+            &ctx.accounts.reward_mint,
+            &ctx.accounts.reward_account,
+            &ctx.accounts.reward_treasury,
+            &ctx.accounts.reward_token_program,
+        )?;
+
+        return_cnft(ctx.accounts.return_cnft_accounts(), &leaf, ctx.remaining_accounts)
+    }
+
+    /// `emergency_unstake` for pNFTs staked with `stake_pnft`
+    pub fn emergency_unstake_pnft(ctx: Context<EmergencyUnstakePnft>) -> Result<()> {
+        let rewarder = &mut ctx.accounts.rewarder;
+        let stake_account = &mut ctx.accounts.stake_account;
+        let vault_account = &mut ctx.accounts.vault_account;
+        let clock = &ctx.accounts.clock;
+
+        forfeit_stake(
+            rewarder,
+            stake_account,
+            vault_account,
+            &ctx.accounts.stake_receipt,
+            clock.unix_timestamp,
+        )?;
+
+        return_pnft(ctx.accounts.return_pnft_accounts())
+    }
+
+    /// `emergency_unstake` for cNFTs staked with `stake_cnft`
+    pub fn emergency_unstake_cnft<'info>(
+        ctx: Context<'_, '_, '_, 'info, EmergencyUnstakeCnft<'info>>,
+        asset_id: Pubkey,
+        leaf: LeafArgs,
+    ) -> Result<()> {
+        let rewarder = &mut ctx.accounts.rewarder;
+        let stake_account = &mut ctx.accounts.stake_account;
+        let vault_account = &mut ctx.accounts.vault_account;
+        let clock = &ctx.accounts.clock;
+
+        if asset_id != anchor_bubblegum::get_asset_id(&ctx.accounts.merkle_tree.key(), leaf.nonce) {
+            return Err(StakingError::InvalidAssetId.into());
+        }
+
+        forfeit_stake(
+            rewarder,
+            stake_account,
+            vault_account,
+            &ctx.accounts.stake_receipt,
+            clock.unix_timestamp,
+        )?;
+
+        return_cnft(ctx.accounts.return_cnft_accounts(), &leaf, ctx.remaining_accounts)
+    }
+
+    pub fn claim(ctx: Context<Claim>) -> Result<()> {
+        let rewarder = &mut ctx.accounts.rewarder;
+        let stake_account = &mut ctx.accounts.stake_account;
+        let reward_mint = &ctx.accounts.reward_mint;
+        let reward_autority = &ctx.accounts.reward_authority;
+        let reward_token_account = &ctx.accounts.reward_account;
+        let reward_treasury = &ctx.accounts.reward_treasury;
+
+        let token_program = &ctx.accounts.token_program;
+        let clock = &ctx.accounts.clock;
+
+        if rewarder.paused.claim {
+            return Err(StakingError::ClaimsPaused.into());
+        }
+
+        let reward_account = token_interface::load_token_account(reward_token_account, token_program.key)?;
+        if reward_account.owner != ctx.accounts.owner.key() {
+            return Err(StakingError::InvalidOwnerForRewardToken.into());
+        }
+        if reward_account.mint != rewarder.reward_mint {
+            return Err(StakingError::InvalidRewardTokenAccount.into());
+        }
+
+        // Calculate and claim any pending rewards
+        settle_rewards(rewarder, stake_account, clock.unix_timestamp)?;
+
+        // anything above the emission cap stays owed until the cap is raised
+        let payout = rewarder
+            .remaining_emission()
+            .map_or(stake_account.claimed_reward, |remaining| {
+                remaining.min(stake_account.claimed_reward)
+            });
+        
+        transfer_reward(
+            payout,
+            rewarder,
+            reward_mint,
+            reward_token_account,
+            reward_treasury,
+            reward_autority,
+            token_program,
+        )?;
+        rewarder.total_outstanding_rewards = rewarder
+            .total_outstanding_rewards
+            .checked_sub(payout)
+            .ok_or(StakingError::MathOverflow)?;
+        stake_account.claimed_reward -= payout;
+        stake_account.last_claimed = clock.unix_timestamp;
+        for nft_staked in stake_account.nfts_staked.iter_mut() {
+            nft_staked.accrued = 0;
+        }
+
+        Ok(())
+    }
+    pub fn check_balance(ctx: Context<Claim>) -> Result<()> {
+        let rewarder = &mut ctx.accounts.rewarder;
+        let stake_account = &mut ctx.accounts.stake_account;
+        let clock = &ctx.accounts.clock;
+
+        // Calculate and claim any pending rewards
+        settle_rewards(rewarder, stake_account, clock.unix_timestamp)?;
+
+        Ok(())
+    }
+}
+
+
+/// The reward earned by a single staked NFT over `elapsed_time` seconds of accrual
+pub fn calculate_reward(
+    rewarder: &NftStakeRewarder,
+    multiplier_bps: u64,
+    weight_bps: u64,
+    num_staked: u16,
+    elapsed_time: i64,
+) -> std::result::Result<u64, StakingError> {
+    if num_staked == 0 || elapsed_time <= 0 {
+        return Ok(0);
+    }
+
+    // the rarity weight scales the lock multiplier, truncated to whole basis points
+    let multiplier_bps = (multiplier_bps as u128)
+        .checked_mul(weight_bps as u128)
+        .ok_or(StakingError::MathOverflow)?
+        / BPS_DENOMINATOR as u128;
+
+    // multiply everything out in u128 and divide once to avoid both overflow and compounding truncation
+    let reward = (rewarder.reward_rate as u128)
+        .checked_mul(elapsed_time as u128)
+        .and_then(|reward| reward.checked_mul(multiplier_bps))
+        .and_then(|reward| reward.checked_mul(rewarder.quantity_multiplier_bps(num_staked) as u128))
+        .ok_or(StakingError::MathOverflow)?
+        / (24 * 3600 * BPS_DENOMINATOR as u128 * BPS_DENOMINATOR as u128);
+
+    u64::try_from(reward).map_err(|_| StakingError::MathOverflow)
+}
+
+/// Accrues the rewards of every NFT in the stake account up to `current_time` and moves them
+/// into its claimable balance
+pub fn settle_rewards(
+    rewarder: &mut NftStakeRewarder,
+    stake_account: &mut NftStakeAccount,
+    current_time: i64,
+) -> std::result::Result<(), StakingError> {
+    // nfts being unbonded no longer count towards the quantity tiers
+    let num_staked = stake_account
+        .nfts_staked
+        .iter()
+        .filter(|nft_staked| nft_staked.unbonding_until.is_none())
+        .count();
+    let num_staked = u16::try_from(num_staked).map_err(|_| StakingError::MathOverflow)?;
+
+    let mut pending_reward: u64 = 0;
+    let accrual_clock = rewarder.accrual_clock_at(current_time);
+    for nft_staked in stake_account.nfts_staked.iter_mut() {
+        // the accrual clock skips the time the rewarder was paused and the time past the end of the emission
+        let to_reward = if nft_staked.unbonding_until.is_none() {
+            calculate_reward(
+                rewarder,
+                nft_staked.multiplier_bps,
+                nft_staked.weight_bps,
+                num_staked,
+                rewarder.accrual_time(&nft_staked.nft_mint, nft_staked.accrual_clock, accrual_clock),
+            )?
+        } else {
+            0
+        };
+        rewarder.prune_whitelist_removals(&nft_staked.nft_mint, Some(accrual_clock));
+        nft_staked.accrued = nft_staked
+            .accrued
+            .checked_add(to_reward)
+            .ok_or(StakingError::MathOverflow)?;
+        // kept through claims, so that early unstake penalties cannot be dodged by claiming first
+        let multiplier_bps = nft_staked.multiplier_bps.max(BPS_DENOMINATOR) as u128;
+        let lock_boost = to_reward as u128 * (multiplier_bps - BPS_DENOMINATOR as u128) / multiplier_bps;
+        nft_staked.accrued_in_lock = nft_staked
+            .accrued_in_lock
+            .checked_add(to_reward)
+            .ok_or(StakingError::MathOverflow)?;
+        nft_staked.lock_boost_accrued_in_lock = nft_staked
+            .lock_boost_accrued_in_lock
+            .checked_add(lock_boost as u64)
+            .ok_or(StakingError::MathOverflow)?;
+        nft_staked.last_accrued = current_time;
+        nft_staked.accrual_clock = accrual_clock;
+        pending_reward = pending_reward
+            .checked_add(to_reward)
+            .ok_or(StakingError::MathOverflow)?;
+    }
+
+    stake_account.claimed_reward = stake_account
+        .claimed_reward
+        .checked_add(pending_reward)
+        .ok_or(StakingError::MathOverflow)?;
+    rewarder.total_outstanding_rewards = rewarder
+        .total_outstanding_rewards
+        .checked_add(pending_reward)
+        .ok_or(StakingError::MathOverflow)?;
+    update_reward_weight(rewarder, stake_account, current_time);
+
+    Ok(())
+}
+
+/// Replaces the weight the stake account adds to the rewarder with the weight of its accruing nfts
+/// from `current_time` on, must follow every settlement and every change to its nfts. Nfts removed
+/// from the whitelist keep their weight, as they accrue again from the moment they are whitelisted again.
+pub fn update_reward_weight(rewarder: &mut NftStakeRewarder, stake_account: &mut NftStakeAccount, current_time: i64) {
+    let reward_weight = stake_account
+        .nfts_staked
+        .iter()
+        .filter(|nft_staked| {
+            nft_staked.unbonding_until.is_none()
+                && (rewarder.is_accruing(&nft_staked.nft_mint) || rewarder.is_removal_recorded(&nft_staked.nft_mint))
+        })
+        .map(|nft_staked| nft_staked.multiplier_bps as u128 * nft_staked.weight_bps as u128 / BPS_DENOMINATOR as u128)
+        .fold(0_u128, u128::saturating_add);
+    let accrual_clock = rewarder.accrual_clock_at(current_time);
+
+    rewarder.total_reward_weight = rewarder
+        .total_reward_weight
+        .saturating_sub(stake_account.reward_weight)
+        .saturating_add(reward_weight);
+    rewarder.total_reward_weight_clock = rewarder
+        .total_reward_weight_clock
+        .saturating_sub(stake_account.reward_weight.saturating_mul(stake_account.reward_weight_clock as u128))
+        .saturating_add(reward_weight.saturating_mul(accrual_clock as u128));
+    stake_account.reward_weight = reward_weight;
+    stake_account.reward_weight_clock = accrual_clock;
+}
+
+/// Converts the vault and stake accounts of an owner from their layout before stake receipts. Staked nfts
+/// have no receipt to be unstaked with, so the legacy accounts must not hold any.
+pub fn migrate_legacy_accounts(
+    rewarder: &mut NftStakeRewarder,
+    legacy_vault_account: LegacyVaultAccount,
+    legacy_stake_account: LegacyNftStakeAccount,
+) -> std::result::Result<(VaultAccount, NftStakeAccount), StakingError> {
+    let is_empty = legacy_vault_account.total_staked == 0
+        && legacy_stake_account
+            .nfts_staked
+            .iter()
+            .all(|nft_staked| nft_staked.num_staked == 0);
+    if !is_empty {
+        return Err(StakingError::LegacyStakeNotEmpty);
+    }
+
+    // rewards settled into the legacy stake account are still owed
+    rewarder.total_outstanding_rewards = rewarder
+        .total_outstanding_rewards
+        .checked_add(legacy_stake_account.claimed_reward)
+        .ok_or(StakingError::MathOverflow)?;
+
+    let vault_account = VaultAccount {
+        total_staked: 0,
+        reward_mint: legacy_vault_account.reward_mint,
+        bump: legacy_vault_account.bump,
+    };
+    let stake_account = NftStakeAccount {
+        owner: legacy_stake_account.owner,
+        rewarder: legacy_stake_account.rewarder,
+        nfts_staked: vec![],
+        bump: legacy_stake_account.bump,
+        last_claimed: legacy_stake_account.last_claimed,
+        claimed_reward: legacy_stake_account.claimed_reward,
+        reward_weight: 0,
+        reward_weight_clock: 0,
+    };
+    Ok((vault_account, stake_account))
+}
+
+/// Records the stake of an nft in its receipt and in the stake account of the owner
+pub fn record_stake(
+    rewarder: &mut NftStakeRewarder,
+    stake_account: &mut NftStakeAccount,
+    vault_account: &mut VaultAccount,
+    stake_receipt: &mut StakeReceipt,
+    nft_mint: Pubkey,
+    locking_period: i64,
+    current_time: i64,
+) -> std::result::Result<(), StakingError> {
+    if rewarder.paused.stake {
+        return Err(StakingError::StakingPaused);
+    }
+    if !rewarder.is_allowed_locking_period(locking_period) {
+        return Err(StakingError::InvalidLockingPeriod);
+    }
+
+    // Calculate and claim any pending rewards
+    settle_rewards(rewarder, stake_account, current_time)?;
+
+    // record the stake of this nft in its receipt
+    stake_receipt.owner = stake_account.owner;
+    stake_receipt.rewarder = stake_account.rewarder;
+    stake_receipt.nft_mint = nft_mint;
+    stake_receipt.start_staking = current_time;
+    stake_receipt.locking_period = locking_period;
+
+    vault_account.total_staked = vault_account
+        .total_staked
+        .checked_add(1)
+        .ok_or(StakingError::MathOverflow)?;
+
+    // add the nft info into the NFTSTAKE
+    if stake_account.nfts_staked.len() >= MAX_NFTS_PER_STAKE_ACCOUNT {
+        return Err(StakingError::TooManyNftsStaked);
+    }
+    stake_account.nfts_staked.push(NftStaked {
+        nft_mint,
+        locking_period,
+        multiplier_bps: rewarder.lock_multiplier_bps(locking_period),
+        weight_bps: stake_receipt.weight_bps,
+        last_accrued: current_time,
+        accrual_clock: rewarder.accrual_clock_at(current_time),
+        accrued: 0,
+        accrued_in_lock: 0,
+        lock_boost_accrued_in_lock: 0,
+        unbonding_until: None,
+    });
+    update_reward_weight(rewarder, stake_account, current_time);
+
+    Ok(())
+}
+
+/// Removes an nft recorded by [`record_stake`] from the stake account of the owner once its lock has ended,
+/// or earlier at the cost of the early unstake policy of the rewarder. When the rewarder has an unbonding
+/// period the nft must have been requested with [`request_release`] and its unbonding period must have elapsed.
+/// Returns the early unstake fee the owner owes.
+pub fn release_stake(
+    rewarder: &mut NftStakeRewarder,
+    stake_account: &mut NftStakeAccount,
+    vault_account: &mut VaultAccount,
+    stake_receipt: &StakeReceipt,
+    current_time: i64,
+) -> std::result::Result<u64, StakingError> {
+    let position = stake_account
+        .nfts_staked
+        .iter()
+        .position(|nft_staked| nft_staked.nft_mint == stake_receipt.nft_mint)
+        .ok_or(StakingError::NftNotStaked)?;
+
+    let fee = match stake_account.nfts_staked[position].unbonding_until {
+        // the lock was checked when unstaking was requested
+        Some(unbonding_until) => {
+            if current_time < unbonding_until {
+                return Err(StakingError::UnbondingNotElapsed);
+            }
+            settle_rewards(rewarder, stake_account, current_time)?;
+            0
+        }
+        None => {
+            if rewarder.unbonding_period > 0 {
+                return Err(StakingError::UnbondingRequired);
+            }
+            // check the locking period recorded when the nft was staked
+            if stake_receipt.is_locked(current_time) && rewarder.early_unstake_policy == EarlyUnstakePolicy::HardLock {
+                return Err(StakingError::NFTAUnlocked);
+            }
+            // Calculate and claim any pending rewards
+            settle_rewards(rewarder, stake_account, current_time)?;
+            charge_early_unstake(rewarder, stake_account, position, stake_receipt, current_time)?
+        }
+    };
+
+    //remove the nft from the stake account
+    stake_account.nfts_staked.swap_remove(position);
+    vault_account.total_staked = vault_account.total_staked.saturating_sub(1);
+    rewarder.prune_whitelist_removals(&stake_receipt.nft_mint, None);
+    update_reward_weight(rewarder, stake_account, current_time);
+
+    Ok(fee)
+}
+
+/// Starts the unbonding period of an nft recorded by [`record_stake`], it stops accruing rewards
+/// and can be released with [`release_stake`] once the period has elapsed. Returns the early unstake fee the owner owes.
+pub fn request_release(
+    rewarder: &mut NftStakeRewarder,
+    stake_account: &mut NftStakeAccount,
+    stake_receipt: &StakeReceipt,
+    current_time: i64,
+) -> std::result::Result<u64, StakingError> {
+    let position = stake_account
+        .nfts_staked
+        .iter()
+        .position(|nft_staked| nft_staked.nft_mint == stake_receipt.nft_mint)
+        .ok_or(StakingError::NftNotStaked)?;
+    if stake_account.nfts_staked[position].unbonding_until.is_some() {
+        return Err(StakingError::UnstakeAlreadyRequested);
+    }
+    // check the locking period recorded when the nft was staked
+    if stake_receipt.is_locked(current_time) && rewarder.early_unstake_policy == EarlyUnstakePolicy::HardLock {
+        return Err(StakingError::NFTAUnlocked);
+    }
+
+    // Calculate and claim any pending rewards
+    settle_rewards(rewarder, stake_account, current_time)?;
+    let fee = charge_early_unstake(rewarder, stake_account, position, stake_receipt, current_time)?;
+
+    stake_account.nfts_staked[position].unbonding_until = Some(
+        current_time
+            .checked_add(rewarder.unbonding_period)
+            .ok_or(StakingError::MathOverflow)?,
+    );
+    update_reward_weight(rewarder, stake_account, current_time);
+
+    Ok(fee)
+}
+
+/// Relocks an nft recorded by [`record_stake`] from now on for `locking_period`, which cannot be
+/// shorter than its current one
+pub fn extend_stake(
+    rewarder: &mut NftStakeRewarder,
+    stake_account: &mut NftStakeAccount,
+    stake_receipt: &mut StakeReceipt,
+    locking_period: i64,
+    current_time: i64,
+) -> std::result::Result<(), StakingError> {
+    if rewarder.paused.stake {
+        return Err(StakingError::StakingPaused);
+    }
+    if !rewarder.is_allowed_locking_period(locking_period) {
+        return Err(StakingError::InvalidLockingPeriod);
+    }
+    let position = stake_account
+        .nfts_staked
+        .iter()
+        .position(|nft_staked| nft_staked.nft_mint == stake_receipt.nft_mint)
+        .ok_or(StakingError::NftNotStaked)?;
+    if stake_account.nfts_staked[position].unbonding_until.is_some() {
+        return Err(StakingError::UnstakeAlreadyRequested);
+    }
+    // the new lock starts now, so it always ends after the current one
+    if locking_period < stake_receipt.locking_period {
+        return Err(StakingError::LockCannotBeShortened);
+    }
+
+    // rewards up to now accrue at the old multiplier
+    settle_rewards(rewarder, stake_account, current_time)?;
+
+    let nft_staked = &mut stake_account.nfts_staked[position];
+    // what accrued during a lock that is still running counts towards the new one
+    if !stake_receipt.is_locked(current_time) {
+        nft_staked.accrued_in_lock = 0;
+        nft_staked.lock_boost_accrued_in_lock = 0;
+    }
+    stake_receipt.start_staking = current_time;
+    stake_receipt.locking_period = locking_period;
+    nft_staked.locking_period = locking_period;
+    nft_staked.multiplier_bps = rewarder.lock_multiplier_bps(locking_period);
+    update_reward_weight(rewarder, stake_account, current_time);
+
+    Ok(())
+}
+
+/// Leaving before the locking period recorded when the nft was staked has passed forfeits part of the
+/// rewards the nft accrued during its lock, which are no longer owed. Returns the fee the owner pays in
+/// reward tokens, including the forfeited rewards that were claimed already
+fn charge_early_unstake(
+    rewarder: &mut NftStakeRewarder,
+    stake_account: &mut NftStakeAccount,
+    position: usize,
+    stake_receipt: &StakeReceipt,
+    current_time: i64,
+) -> std::result::Result<u64, StakingError> {
+    if !stake_receipt.is_locked(current_time) {
+        return Ok(0);
+    }
+
+    // forfeited rewards come out of the unclaimed balance first, the owner pays back whatever was claimed already
+    let forfeited = rewarder
+        .early_unstake_policy
+        .forfeited_rewards(&stake_account.nfts_staked[position])?;
+    let unclaimed_forfeited = forfeited.min(stake_account.claimed_reward);
+    stake_account.nfts_staked[position].accrued -= unclaimed_forfeited.min(stake_account.nfts_staked[position].accrued);
+    stake_account.claimed_reward -= unclaimed_forfeited;
+    rewarder.total_outstanding_rewards = rewarder.total_outstanding_rewards.saturating_sub(unclaimed_forfeited);
+
+    rewarder
+        .early_unstake_policy
+        .fee()
+        .checked_add(forfeited - unclaimed_forfeited)
+        .ok_or(StakingError::MathOverflow)
+}
+
+/// Removes an nft from the stake account of the owner without settling any rewards, forfeiting
+/// the rewards of the owner that have not been claimed yet
+pub fn forfeit_stake(
+    rewarder: &mut NftStakeRewarder,
+    stake_account: &mut NftStakeAccount,
+    vault_account: &mut VaultAccount,
+    stake_receipt: &StakeReceipt,
+    current_time: i64,
+) -> std::result::Result<(), StakingError> {
+    let position = stake_account
+        .nfts_staked
+        .iter()
+        .position(|nft_staked| nft_staked.nft_mint == stake_receipt.nft_mint)
+        .ok_or(StakingError::NftNotStaked)?;
+
+    // the lock and the unbonding period still apply, only the rewards are skipped
+    match stake_account.nfts_staked[position].unbonding_until {
+        Some(unbonding_until) => {
+            if current_time < unbonding_until {
+                return Err(StakingError::UnbondingNotElapsed);
+            }
+        }
+        None => {
+            if rewarder.unbonding_period > 0 {
+                return Err(StakingError::UnbondingRequired);
+            }
+            if stake_receipt.is_locked(current_time) {
+                return Err(StakingError::NFTAUnlocked);
+            }
+        }
+    }
+
+    stake_account.nfts_staked.swap_remove(position);
+    vault_account.total_staked = vault_account.total_staked.saturating_sub(1);
+    rewarder.prune_whitelist_removals(&stake_receipt.nft_mint, None);
+
+    // the remaining nfts start accruing again from now
+    let accrual_clock = rewarder.accrual_clock_at(current_time);
+    for nft_staked in stake_account.nfts_staked.iter_mut() {
+        nft_staked.accrued = 0;
+        nft_staked.last_accrued = current_time;
+        nft_staked.accrual_clock = accrual_clock;
+    }
+    rewarder.total_outstanding_rewards = rewarder
+        .total_outstanding_rewards
+        .saturating_sub(stake_account.claimed_reward);
+    stake_account.claimed_reward = 0;
+    update_reward_weight(rewarder, stake_account, current_time);
+
+    Ok(())
+}
+
+/// The accounts handing a staked nft back to its owner, shared by `unstake_nft`, `complete_unstake`
+/// and `emergency_unstake`
+pub struct ReturnNft<'a, 'info> {
+    pub owner: &'a AccountInfo<'info>,
+    pub rewarder: &'a Account<'info, NftStakeRewarder>,
+    pub stake_account: &'a Account<'info, NftStakeAccount>,
+    pub nft_mint: &'a AccountInfo<'info>,
+    pub nft_token_account: &'a AccountInfo<'info>,
+    pub nft_escrow: &'a AccountInfo<'info>,
+    pub nft_edition: &'a AccountInfo<'info>,
+    pub token_program: &'a AccountInfo<'info>,
+    pub token_metadata_program: &'a Program<'info, MetaplexTokenMetadata>,
+}
+
+/// The accounts handing a staked pnft back to its owner, shared by `unstake_pnft` and `emergency_unstake_pnft`
+pub struct ReturnPnft<'a, 'info> {
+    pub owner: &'a AccountInfo<'info>,
+    pub rewarder: &'a Account<'info, NftStakeRewarder>,
+    pub stake_account: &'a Account<'info, NftStakeAccount>,
+    pub nft_mint: &'a Account<'info, Mint>,
+    pub nft_token_account: &'a Account<'info, TokenAccount>,
+    pub nft_metadata: &'a AccountInfo<'info>,
+    pub nft_edition: &'a AccountInfo<'info>,
+    pub nft_token_record: &'a AccountInfo<'info>,
+    pub authorization_rules_program: &'a AccountInfo<'info>,
+    pub authorization_rules: &'a AccountInfo<'info>,
+    pub sysvar_instructions: &'a AccountInfo<'info>,
+    pub token_program: &'a Program<'info, Token>,
+    pub token_metadata_program: &'a Program<'info, MetaplexTokenMetadata>,
+    pub system_program: &'a Program<'info, System>,
+}
+
+/// The accounts handing a staked cnft back to its owner, shared by `unstake_cnft` and `emergency_unstake_cnft`
+pub struct ReturnCnft<'a, 'info> {
+    pub owner: &'a AccountInfo<'info>,
+    pub rewarder: &'a Account<'info, NftStakeRewarder>,
+    pub stake_account: &'a Account<'info, NftStakeAccount>,
+    pub tree_config: &'a AccountInfo<'info>,
+    pub merkle_tree: &'a AccountInfo<'info>,
+    pub bubblegum_program: &'a Program<'info, Bubblegum>,
+    pub compression_program: &'a Program<'info, SplAccountCompression>,
+    pub log_wrapper: &'a Program<'info, SplNoop>,
+    pub system_program: &'a Program<'info, System>,
+}
+
+/// Hands a staked nft back to its owner according to the stake mode of the rewarder
+pub fn return_nft(accounts: ReturnNft) -> Result<()> {
+    let owner = accounts.owner;
+    let rewarder = accounts.rewarder;
+    let stake_account = accounts.stake_account;
+    let nft_token_account = accounts.nft_token_account;
+    let token_program = accounts.token_program;
+
+    let nft_mint = token_interface::load_mint(accounts.nft_mint, token_program.key)?;
+    let nft_token_account_state = token_interface::load_token_account(nft_token_account, token_program.key)?;
+    if nft_token_account_state.mint != accounts.nft_mint.key() {
+        return Err(StakingError::InvalidNFTAccountMint.into());
+    }
+
+    let stake_account_seeds = &[
+        rewarder.collection.as_bytes(),
+        &id().to_bytes(),
+        ACCOUNT_PREFIX,
+        &rewarder.key().to_bytes(),
+        &owner.key().to_bytes(),
+        &[stake_account.bump],
+    ];
+
+    let stake_account_signer = &[&stake_account_seeds[..]];
+
+    match rewarder.stake_mode {
+        StakeMode::SetAuthority => {
+            if nft_token_account_state.owner != stake_account.key() {
+                return Err(StakingError::InvalidStakedNFTOwner.into());
+            }
+
+            //transfer nft to vault
+            let authority_accounts = SetAuthority {
+                current_authority: stake_account.to_account_info(),
+                account_or_mint: nft_token_account.to_account_info(),
+            };
+            let authority_ctx = CpiContext::new_with_signer(
+                token_program.to_account_info(),
+                authority_accounts,
+                stake_account_signer,
+            );
+            token::set_authority(
+                authority_ctx,
+                AuthorityType::AccountOwner,
+                Some(owner.key()),
+            )?;
+        }
+        StakeMode::Escrow => {
+            if nft_token_account_state.owner != owner.key() {
+                return Err(StakingError::InvalidNFTOwner.into());
+            }
+
+            //return the nft from the escrow and close it
+            let nft_escrow = accounts.nft_escrow;
+            let transfer_accounts = token_interface::TransferChecked {
+                from: nft_escrow.to_account_info(),
+                mint: accounts.nft_mint.to_account_info(),
+                to: nft_token_account.to_account_info(),
+                authority: stake_account.to_account_info(),
+            };
+            let transfer_ctx = CpiContext::new_with_signer(
+                token_program.to_account_info(),
+                transfer_accounts,
+                stake_account_signer,
+            );
+            token_interface::transfer_checked(transfer_ctx, 1, nft_mint.decimals)?;
+
+            let close_accounts = token_interface::CloseAccount {
+                account: nft_escrow.to_account_info(),
+                destination: owner.to_account_info(),
+                authority: stake_account.to_account_info(),
+            };
+            let close_ctx = CpiContext::new_with_signer(
+                token_program.to_account_info(),
+                close_accounts,
+                stake_account_signer,
+            );
+            token_interface::close_account(close_ctx)?;
+        }
+        StakeMode::Freeze => {
+            if nft_token_account_state.owner != owner.key()
+                || !nft_token_account_state.delegate.contains(&stake_account.key())
+            {
+                return Err(StakingError::InvalidStakedNFTOwner.into());
+            }
+
+            //thaw the nft and hand control back to the owner
+            let thaw_accounts = anchor_metaplex::DelegatedAccount {
+                delegate: stake_account.to_account_info(),
+                token_account: nft_token_account.to_account_info(),
+                edition: accounts.nft_edition.to_account_info(),
+                mint: accounts.nft_mint.to_account_info(),
+                token_program: token_program.to_account_info(),
+            };
+            let thaw_ctx = CpiContext::new_with_signer(
+                accounts.token_metadata_program.to_account_info(),
+                thaw_accounts,
+                stake_account_signer,
+            );
+            anchor_metaplex::thaw_delegated_account(thaw_ctx)?;
+
+            let revoke_accounts = Revoke {
+                source: nft_token_account.to_account_info(),
+                authority: owner.to_account_info(),
+            };
+            let revoke_ctx = CpiContext::new(token_program.to_account_info(), revoke_accounts);
+            token::revoke(revoke_ctx)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Unlocks a staked pnft and revokes the stake account as its delegate
+pub fn return_pnft(accounts: ReturnPnft) -> Result<()> {
+    let owner = accounts.owner;
+    let rewarder = accounts.rewarder;
+    let stake_account = accounts.stake_account;
+    let nft_mint = accounts.nft_mint;
+
+    //unlock the pnft with the stake account
+    let stake_account_seeds = &[
+        rewarder.collection.as_bytes(),
+        &id().to_bytes(),
+        ACCOUNT_PREFIX,
+        &rewarder.key().to_bytes(),
+        &owner.key().to_bytes(),
+        &[stake_account.bump],
+    ];
+    let stake_account_signer = &[&stake_account_seeds[..]];
+    let unlock_accounts = anchor_metaplex::LockToken {
+        authority: stake_account.to_account_info(),
+        token_owner: owner.to_account_info(),
+        token: accounts.nft_token_account.to_account_info(),
+        mint: nft_mint.to_account_info(),
+        metadata: accounts.nft_metadata.to_account_info(),
+        edition: accounts.nft_edition.to_account_info(),
+        token_record: accounts.nft_token_record.to_account_info(),
+        payer: owner.to_account_info(),
+        system_program: accounts.system_program.to_account_info(),
+        sysvar_instructions: accounts.sysvar_instructions.to_account_info(),
+        spl_token_program: accounts.token_program.to_account_info(),
+        authorization_rules_program: accounts.authorization_rules_program.to_account_info(),
+        authorization_rules: accounts.authorization_rules.to_account_info(),
+    };
+    let unlock_ctx = CpiContext::new_with_signer(
+        accounts.token_metadata_program.to_account_info(),
+        unlock_accounts,
+        stake_account_signer,
+    );
+    anchor_metaplex::unlock(unlock_ctx)?;
+
+    //hand the pnft back to the owner
+    let revoke_accounts = anchor_metaplex::TokenDelegate {
+        delegate: stake_account.to_account_info(),
+        metadata: accounts.nft_metadata.to_account_info(),
+        master_edition: accounts.nft_edition.to_account_info(),
+        token_record: accounts.nft_token_record.to_account_info(),
+        mint: nft_mint.to_account_info(),
+        token: accounts.nft_token_account.to_account_info(),
+        authority: owner.to_account_info(),
+        payer: owner.to_account_info(),
+        system_program: accounts.system_program.to_account_info(),
+        sysvar_instructions: accounts.sysvar_instructions.to_account_info(),
+        spl_token_program: accounts.token_program.to_account_info(),
+        authorization_rules_program: accounts.authorization_rules_program.to_account_info(),
+        authorization_rules: accounts.authorization_rules.to_account_info(),
+    };
+    let revoke_ctx = CpiContext::new(
+        accounts.token_metadata_program.to_account_info(),
+        revoke_accounts,
+    );
+    anchor_metaplex::revoke_staking(revoke_ctx)?;
+
+    Ok(())
+}
+
+/// Transfers a staked cnft back to its owner, bubblegum verifies the leaf against the `proof` nodes
+pub fn return_cnft<'info>(accounts: ReturnCnft<'_, 'info>, leaf: &LeafArgs, proof: &[AccountInfo<'info>]) -> Result<()> {
+    let owner = accounts.owner;
+    let rewarder = accounts.rewarder;
+    let stake_account = accounts.stake_account;
+
+    //transfer the leaf back to the owner
+    let stake_account_seeds = &[
+        rewarder.collection.as_bytes(),
+        &id().to_bytes(),
+        ACCOUNT_PREFIX,
+        &rewarder.key().to_bytes(),
+        &owner.key().to_bytes(),
+        &[stake_account.bump],
+    ];
+    let stake_account_signer = &[&stake_account_seeds[..]];
+    let transfer_accounts = anchor_bubblegum::Transfer {
+        tree_config: accounts.tree_config.to_account_info(),
+        leaf_owner: stake_account.to_account_info(),
+        leaf_delegate: stake_account.to_account_info(),
+        new_leaf_owner: owner.to_account_info(),
+        merkle_tree: accounts.merkle_tree.to_account_info(),
+        log_wrapper: accounts.log_wrapper.to_account_info(),
+        compression_program: accounts.compression_program.to_account_info(),
+        system_program: accounts.system_program.to_account_info(),
+    };
+    let transfer_ctx = CpiContext::new_with_signer(
+        accounts.bubblegum_program.to_account_info(),
+        transfer_accounts,
+        stake_account_signer,
+    )
+    .with_remaining_accounts(proof.to_vec());
+    anchor_bubblegum::transfer(transfer_ctx, leaf)?;
+
+    Ok(())
+}
+
+pub fn transfer_reward<'info>(
     earned_reward: u64,
-    rewarder: &Account<'info, NftStakeRewarder>,
-    reward_mint: &Account<'info, Mint>,
-    reward_account: &Account<'info, TokenAccount>,
-    mint_authority: &AccountInfo<'info>,
+    rewarder: &mut Account<'info, NftStakeRewarder>,
+    reward_mint: &AccountInfo<'info>,
+    reward_account: &AccountInfo<'info>,
+    reward_treasury: &AccountInfo<'info>,
+    reward_authority: &AccountInfo<'info>,
     token_program: &AccountInfo<'info>,
 ) -> Result<()> {
-    let mint_authority_seeds = &[
+    let reward_authority_seeds = &[
         rewarder.collection.as_bytes(),
         &id().to_bytes(),
         REWARDER_PREFIX,
         &rewarder.key().to_bytes(),
         &[rewarder.reward_authority_bump],
     ];
-    let mint_authority_signer = &[&mint_authority_seeds[..]];
-    let mint_accounts = MintTo {
-        mint: reward_mint.to_account_info(),
-        to: reward_account.to_account_info(),
-        authority: mint_authority.to_account_info(),
+    let reward_authority_signer = &[&reward_authority_seeds[..]];
+
+    match rewarder.reward_source {
+        RewardSource::Mint => {
+            let mint_accounts = token_interface::MintTo {
+                mint: reward_mint.to_account_info(),
+                to: reward_account.to_account_info(),
+                authority: reward_authority.to_account_info(),
+            };
+            let mint_ctx = CpiContext::new_with_signer(
+                token_program.to_account_info(),
+                mint_accounts,
+                reward_authority_signer,
+            );
+            token_interface::mint_to(mint_ctx, earned_reward)?;
+        }
+        RewardSource::Treasury => {
+            let mint = token_interface::load_mint(reward_mint, token_program.key)?;
+            let treasury = token_interface::load_token_account(reward_treasury, token_program.key)?;
+            if treasury.amount < earned_reward {
+                return Err(StakingError::InsufficientTreasuryBalance.into());
+            }
+            // transfer fees of the reward mint are withheld from the reward
+            let transfer_accounts = token_interface::TransferChecked {
+                from: reward_treasury.to_account_info(),
+                mint: reward_mint.to_account_info(),
+                to: reward_account.to_account_info(),
+                authority: reward_authority.to_account_info(),
+            };
+            let transfer_ctx = CpiContext::new_with_signer(
+                token_program.to_account_info(),
+                transfer_accounts,
+                reward_authority_signer,
+            );
+            token_interface::transfer_checked(transfer_ctx, earned_reward, mint.decimals)?;
+        }
+    }
+
+    rewarder.total_emitted = rewarder
+        .total_emitted
+        .checked_add(earned_reward)
+        .ok_or(StakingError::MathOverflow)?;
+    Ok(())
+}
+
+/// Takes the early unstake fee from the reward token account of the owner, into the treasury of
+/// treasury backed rewarders and burnt otherwise
+pub fn pay_early_unstake_fee<'info>(
+    fee: u64,
+    rewarder: &NftStakeRewarder,
+    owner: &AccountInfo<'info>,
+    reward_mint: &AccountInfo<'info>,
+    reward_account: &AccountInfo<'info>,
+    reward_treasury: &AccountInfo<'info>,
+    reward_token_program: &AccountInfo<'info>,
+) -> Result<()> {
+    if fee == 0 {
+        return Ok(());
+    }
+    let mint = token_interface::load_mint(reward_mint, reward_token_program.key)?;
+    let account = token_interface::load_token_account(reward_account, reward_token_program.key)?;
+    if account.owner != owner.key() {
+        return Err(StakingError::InvalidOwnerForRewardToken.into());
+    }
+    if account.mint != rewarder.reward_mint {
+        return Err(StakingError::InvalidRewardTokenAccount.into());
+    }
+
+    match rewarder.reward_source {
+        RewardSource::Mint => {
+            let burn_accounts = token_interface::Burn {
+                from: reward_account.to_account_info(),
+                mint: reward_mint.to_account_info(),
+                authority: owner.to_account_info(),
+            };
+            let burn_ctx = CpiContext::new(reward_token_program.to_account_info(), burn_accounts);
+            token_interface::burn(burn_ctx, fee)
+        }
+        RewardSource::Treasury => {
+            let transfer_accounts = token_interface::TransferChecked {
+                from: reward_account.to_account_info(),
+                mint: reward_mint.to_account_info(),
+                to: reward_treasury.to_account_info(),
+                authority: owner.to_account_info(),
+            };
+            let transfer_ctx = CpiContext::new(reward_token_program.to_account_info(), transfer_accounts);
+            token_interface::transfer_checked(transfer_ctx, fee, mint.decimals)
+        }
+    }
+}
+
+/// Creates an account at a PDA of this program. An address that already holds lamports is topped up
+/// and allocated instead, so sending lamports to the PDA ahead of time cannot block its creation
+pub fn create_pda_account<'info>(
+    payer: &AccountInfo<'info>,
+    account: &AccountInfo<'info>,
+    space: usize,
+    owner: &Pubkey,
+    system_program: &AccountInfo<'info>,
+    rent: &Rent,
+    signer_seeds: &[&[&[u8]]],
+) -> Result<()> {
+    let lamports = rent.minimum_balance(space);
+    if account.lamports() == 0 {
+        let create_accounts = anchor_lang::system_program::CreateAccount {
+            from: payer.to_account_info(),
+            to: account.to_account_info(),
+        };
+        let create_ctx =
+            CpiContext::new_with_signer(system_program.to_account_info(), create_accounts, signer_seeds);
+        return anchor_lang::system_program::create_account(create_ctx, lamports, space as u64, owner);
+    }
+
+    let top_up = lamports.saturating_sub(account.lamports());
+    if top_up > 0 {
+        let transfer_accounts = anchor_lang::system_program::Transfer {
+            from: payer.to_account_info(),
+            to: account.to_account_info(),
+        };
+        let transfer_ctx = CpiContext::new(system_program.to_account_info(), transfer_accounts);
+        anchor_lang::system_program::transfer(transfer_ctx, top_up)?;
+    }
+    let allocate_accounts = anchor_lang::system_program::Allocate {
+        account_to_allocate: account.to_account_info(),
     };
-    let mint_ctx = CpiContext::new_with_signer(
-        token_program.to_account_info(),
-        mint_accounts,
-        mint_authority_signer,
-    );
-    token::mint_to(mint_ctx, earned_reward)
+    let allocate_ctx =
+        CpiContext::new_with_signer(system_program.to_account_info(), allocate_accounts, signer_seeds);
+    anchor_lang::system_program::allocate(allocate_ctx, space as u64)?;
+    let assign_accounts = anchor_lang::system_program::Assign {
+        account_to_assign: account.to_account_info(),
+    };
+    let assign_ctx =
+        CpiContext::new_with_signer(system_program.to_account_info(), assign_accounts, signer_seeds);
+    anchor_lang::system_program::assign(assign_ctx, owner)
+}
+
+#[derive(Accounts)]
+#[instruction(_rewarder_bump: u8, reward_authority_bump: u8, reward_rate: u64, collection: String, creators: Vec<CreatorStruct>)]
+pub struct InitializeRewarder<'info> {
+    /// The new rewarder account to create
+    #[account(
+        init,
+        space = 10240,
+        payer = authority,
+        seeds = [collection.as_bytes(), &id().to_bytes(), REWARDER_PREFIX],
+        bump
+    )]
+    pub rewarder: Account<'info, NftStakeRewarder>,
+
+    /// The owner of the rewarder account
+    /// CHECK:` doc comment explaining why no checks through types are necessary.
+    #[account(mut, signer)]
+    pub authority: AccountInfo<'info>,
+
+    /// PDA used for minting rewards
+    #[account(
+        seeds = [collection.as_bytes(), &id().to_bytes(), REWARDER_PREFIX, &rewarder.key().to_bytes()],
+        bump = reward_authority_bump,
+    )]
+    /// CHECK:` doc comment explaining why no checks through types are necessary.
+    pub reward_authority: AccountInfo<'info>,
+
+    /// The Mint of the reward token, under either token program. Must have the reward authority
+    /// mint authority unless rewards are paid from the treasury
+    /// CHECK: unpacked and checked in the handler
+    pub reward_mint: AccountInfo<'info>,
+
+    /// The token account holding the rewards, only created for treasury backed rewarders
+    /// CHECK: created in the handler with the space the extensions of the reward mint require
+    #[account(
+        mut,
+        seeds = [collection.as_bytes(), &id().to_bytes(), TREASURY_PREFIX, &rewarder.key().to_bytes()],
+        bump,
+    )]
+    pub reward_treasury: AccountInfo<'info>,
+
+    /// The legacy token program or Token-2022
+    /// CHECK: either token program, checked by the constraint
+    #[account(constraint = token_interface::is_token_program(token_program.key) @ StakingError::InvalidTokenProgram)]
+    pub token_program: AccountInfo<'info>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateRewardRate<'info> {
+    /// The new rewarder account to updtae
+    #[account(
+        mut,
+        has_one = authority @ StakingError::InvalidRewarderAuthority,
+    )]
+    pub rewarder: Account<'info, NftStakeRewarder>,
+
+    /// The owner of the rewarder account
+    /// CHECK:` doc comment explaining why no checks through types are necessary.
+    #[account(signer)]
+    pub authority: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetPaused<'info> {
+    /// The rewarder account to pause
+    #[account(
+        mut,
+        constraint = rewarder.authority == signer.key() || rewarder.guardian == Some(signer.key()) @ StakingError::InvalidPauseAuthority,
+    )]
+    pub rewarder: Account<'info, NftStakeRewarder>,
+
+    /// The authority or the guardian of the rewarder
+    /// CHECK: checked against the rewarder by the constraint
+    #[account(signer)]
+    pub signer: AccountInfo<'info>,
+
+    pub clock: Sysvar<'info, Clock>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptAuthority<'info> {
+    /// The rewarder account to take over
+    #[account(
+        mut,
+        constraint = rewarder.pending_authority.is_some() @ StakingError::NoPendingAuthority,
+        constraint = rewarder.pending_authority == Some(pending_authority.key()) @ StakingError::InvalidPendingAuthority,
+    )]
+    pub rewarder: Account<'info, NftStakeRewarder>,
+
+    /// The authority proposed by the current owner of the rewarder
+    /// CHECK: checked against the pending authority of the rewarder
+    #[account(signer)]
+    pub pending_authority: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct FundRewarder<'info> {
+    /// The rewarder account to fund
+    #[account(
+        has_one = authority @ StakingError::InvalidRewarderAuthority,
+    )]
+    pub rewarder: Account<'info, NftStakeRewarder>,
+
+    /// The owner of the rewarder account
+    /// CHECK:` doc comment explaining why no checks through types are necessary.
+    #[account(signer)]
+    pub authority: AccountInfo<'info>,
+
+    /// The Mint of the rewarded token
+    /// CHECK: unpacked in the handler
+    #[account(address = rewarder.reward_mint @ StakingError::InvalidRewardMint)]
+    pub reward_mint: AccountInfo<'info>,
+
+    /// The reward token account the deposit is taken from
+    /// CHECK: unpacked and checked in the handler
+    #[account(mut)]
+    pub authority_token_account: AccountInfo<'info>,
+
+    /// The token account holding the rewards for treasury backed rewarders
+    /// CHECK: the treasury PDA created with the rewarder
+    #[account(
+        mut,
+        seeds = [rewarder.collection.as_bytes(), &id().to_bytes(), TREASURY_PREFIX, &rewarder.key().to_bytes()],
+        bump = rewarder.reward_treasury_bump,
+    )]
+    pub reward_treasury: AccountInfo<'info>,
+
+    /// The legacy token program or Token-2022
+    /// CHECK: either token program, checked by the constraint
+    #[account(constraint = token_interface::is_token_program(token_program.key) @ StakingError::InvalidTokenProgram)]
+    pub token_program: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawUnallocatedRewards<'info> {
+    /// The rewarder account to withdraw from
+    #[account(
+        has_one = authority @ StakingError::InvalidRewarderAuthority,
+    )]
+    pub rewarder: Account<'info, NftStakeRewarder>,
+
+    /// The owner of the rewarder account
+    /// CHECK:` doc comment explaining why no checks through types are necessary.
+    #[account(signer)]
+    pub authority: AccountInfo<'info>,
+
+    /// The Mint of the rewarded token
+    /// CHECK: unpacked in the handler
+    #[account(address = rewarder.reward_mint @ StakingError::InvalidRewardMint)]
+    pub reward_mint: AccountInfo<'info>,
+
+    /// The reward token account the withdrawal is sent to
+    /// CHECK: unpacked and checked in the handler
+    #[account(mut)]
+    pub authority_token_account: AccountInfo<'info>,
+
+    /// The token account holding the rewards for treasury backed rewarders
+    /// CHECK: the treasury PDA created with the rewarder
+    #[account(
+        mut,
+        seeds = [rewarder.collection.as_bytes(), &id().to_bytes(), TREASURY_PREFIX, &rewarder.key().to_bytes()],
+        bump = rewarder.reward_treasury_bump,
+    )]
+    pub reward_treasury: AccountInfo<'info>,
+
+    /// PDA that owns the reward treasury
+    #[account(
+        seeds = [rewarder.collection.as_bytes(), &id().to_bytes(), REWARDER_PREFIX, &rewarder.key().to_bytes()],
+        bump = rewarder.reward_authority_bump,
+    )]
+    /// CHECK:` doc comment explaining why no checks through types are necessary.
+    pub reward_authority: AccountInfo<'info>,
+
+    /// The legacy token program or Token-2022
+    /// CHECK: either token program, checked by the constraint
+    #[account(constraint = token_interface::is_token_program(token_program.key) @ StakingError::InvalidTokenProgram)]
+    pub token_program: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(bump: u8)]
+pub struct InitializeStakeAccount<'info> {
+    /// The owner of the stake account
+    /// CHECK:` doc comment explaining why no checks through types are necessary.
+    #[account(mut, signer)]
+    pub owner: AccountInfo<'info>,
+
+    /// The new stake account to initialize
+    #[account(
+        init,
+        payer = owner,
+        space = 9000,
+        seeds = [rewarder.collection.as_bytes(), &id().to_bytes(), ACCOUNT_PREFIX, &rewarder.key().to_bytes(), &owner.key().to_bytes()],
+        bump
+    )]
+    pub stake_account: Account<'info, NftStakeAccount>,
+
+    /// The rewarder associated with this stake account
+    pub rewarder: Account<'info, NftStakeRewarder>,
+
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct MigrateStakeAccount<'info> {
+    /// The owner of the accounts, refunded the rent the vault account no longer needs
+    /// CHECK:` doc comment explaining why no checks through types are necessary.
+    #[account(mut, signer)]
+    pub owner: AccountInfo<'info>,
+
+    /// The rewarder of the stake account, owing the rewards it holds
+    #[account(mut)]
+    pub rewarder: Account<'info, NftStakeRewarder>,
+
+    /// The stake account of the owner in its legacy layout
+    /// CHECK: read as a legacy stake account by the instruction
+    #[account(
+        mut,
+        seeds = [rewarder.collection.as_bytes(), &id().to_bytes(), ACCOUNT_PREFIX, &rewarder.key().to_bytes(), &owner.key().to_bytes()],
+        bump,
+    )]
+    pub stake_account: AccountInfo<'info>,
+
+    /// The vault account of the owner in its legacy layout
+    /// CHECK: read as a legacy vault account by the instruction
+    #[account(
+        mut,
+        seeds = [rewarder.collection.as_bytes(), &id().to_bytes(), VAULT_PREFIX, &owner.key().to_bytes()],
+        bump,
+    )]
+    pub vault_account: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(bump: u8)]
+pub struct InitializeVaultAccount<'info> {
+  #[account(mut, signer)]
+  /// CHECK:` doc comment explaining why no checks through types are necessary.
+  pub owner: AccountInfo<'info>,
+
+  #[account(
+    init,
+    space = VaultAccount::LEN,
+    payer = owner,
+    seeds = [rewarder.collection.as_bytes(), &id().to_bytes(), VAULT_PREFIX,&owner.key().to_bytes()],
+    bump
+    )]
+  pub vault_account: Account<'info, VaultAccount>,
+
+  #[account(address = rewarder.reward_mint @ StakingError::InvalidRewardMint)]
+  /// CHECK: the reward mint of the rewarder
+  pub reward_mint: AccountInfo<'info>,
+
+  pub rewarder: Account<'info, NftStakeRewarder>,
+
+
+  pub system_program: Program <'info, System>,
+  pub rent: Sysvar<'info, Rent>,
+}
+
+
+#[derive(Accounts)]
+// #[instruction(_vault_bump: u8)]
+pub struct StakeNft<'info> {
+    /// The owner of the stake account
+    /// CHECK:` doc comment explaining why no checks through types are necessary.
+    #[account(mut, signer)]
+    pub owner: AccountInfo<'info>,
+
+    /// The rewarder account for the collection
+    #[account(mut)]
+    pub rewarder: Box<Account<'info, NftStakeRewarder>>,
+
+    /// PDA that has the authority to mint reward tokens
+    #[account(
+        seeds = [rewarder.collection.as_bytes(), &id().to_bytes(), REWARDER_PREFIX, &rewarder.key().to_bytes()],
+        bump = rewarder.reward_authority_bump,
+    )]
+    /// CHECK:` doc comment explaining why no checks through types are necessary.
+    pub reward_authority: AccountInfo<'info>,
+
+    /// The stake account for the owner
+    #[account(
+        mut,
+        has_one = rewarder @ StakingError::InvalidRewarder,
+        has_one = owner @ StakingError::InvalidOwnerForStakeAccount,
+        seeds = [rewarder.collection.as_bytes(), &id().to_bytes(), ACCOUNT_PREFIX, &rewarder.key().to_bytes(), &owner.key().to_bytes()],
+        bump = stake_account.bump,
+    )]
+    pub stake_account: Account<'info, NftStakeAccount>,
+
+    /// The stake account for the owner
+    #[account(
+    mut,
+    seeds = [rewarder.collection.as_bytes(),&id().to_bytes(), VAULT_PREFIX,&owner.key().to_bytes()],
+    bump = vault_account.bump,
+    )]
+    pub vault_account: Account<'info, VaultAccount>,
+
+    /// The receipt recording the stake of the NFT
+    #[account(
+        init,
+        payer = owner,
+        space = StakeReceipt::LEN,
+        seeds = [rewarder.collection.as_bytes(), &id().to_bytes(), RECEIPT_PREFIX, &rewarder.key().to_bytes(), &nft_mint.key().to_bytes()],
+        bump,
+    )]
+    pub stake_receipt: Box<Account<'info, StakeReceipt>>,
+
+    /// The Mint of the NFT, under either token program
+    /// CHECK: unpacked and checked in the handler
+    pub nft_mint: AccountInfo<'info>,
+
+    /// The token account from the owner
+    /// CHECK: unpacked and checked in the handler
+    #[account(mut)]
+    pub nft_token_account: AccountInfo<'info>,
+
+    /// The escrow token account of the stake account, only used by escrow rewarders
+    #[account(
+        mut,
+        address = token_interface::get_associated_token_address(&stake_account.key(), &nft_mint.key(), token_program.key),
+    )]
+    /// CHECK:` doc comment explaining why no checks through types are necessary.
+    pub nft_escrow: AccountInfo<'info>,
+
+    /// The master edition of the NFT, only used by freeze rewarders
+    /// CHECK: verified by the Token Metadata program when freezing
+    pub nft_edition: AccountInfo<'info>,
+
+    /// The legacy token program or Token-2022
+    /// CHECK: either token program, checked by the constraint
+    #[account(constraint = token_interface::is_token_program(token_program.key) @ StakingError::InvalidTokenProgram)]
+    pub token_program: AccountInfo<'info>,
+    pub token_metadata_program: Program<'info, MetaplexTokenMetadata>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+    pub clock: Sysvar<'info, Clock>,
 }
 
 #[derive(Accounts)]
-#[instruction(_rewarder_bump: u8, reward_authority_bump: u8, reward_rate: u64, collection: String, creators: Vec<CreatorStruct>)]
-pub struct InitializeRewarder<'info> {
-    /// The new rewarder account to create
+pub struct UnstakeNft<'info> {
+    /// The owner of the stake account
+    /// CHECK:` doc comment explaining why no checks through types are necessary.
+    #[account(mut, signer)]
+    pub owner: AccountInfo<'info>,
+
+    /// The rewarder account for the collection
+    /// CHECK:` doc comment explaining why no checks through types are necessary.
+    #[account(mut)]
+    pub rewarder: Account<'info, NftStakeRewarder>,
+
+    /// The stake account for the owner
     #[account(
-        init,
-        space = 10240,
-        payer = authority,
-        seeds = [collection.as_bytes(), &id().to_bytes(), REWARDER_PREFIX],
-        bump
+        mut,
+        has_one = rewarder @ StakingError::InvalidRewarder,
+        has_one = owner @ StakingError::InvalidOwnerForStakeAccount,
+        seeds = [rewarder.collection.as_bytes(), &id().to_bytes(), ACCOUNT_PREFIX, &rewarder.key().to_bytes(), &owner.key().to_bytes()],
+        bump = stake_account.bump,
+    )]
+    pub stake_account: Account<'info, NftStakeAccount>,
+
+    /// The Mint of the NFT, under either token program
+    /// CHECK: unpacked in the handler
+    pub nft_mint: AccountInfo<'info>,
+
+    /// The token account from the owner
+    /// CHECK: unpacked and checked in the handler
+    #[account(
+        mut,
+        address = token_interface::get_associated_token_address(&owner.key(), &nft_mint.key(), token_program.key),
+    )]
+    pub nft_token_account: AccountInfo<'info>,
+
+    /// The escrow token account of the stake account, only used by escrow rewarders
+    #[account(
+        mut,
+        address = token_interface::get_associated_token_address(&stake_account.key(), &nft_mint.key(), token_program.key),
+    )]
+    /// CHECK:` doc comment explaining why no checks through types are necessary.
+    pub nft_escrow: AccountInfo<'info>,
+
+    /// The master edition of the NFT, only used by freeze rewarders
+    /// CHECK: verified by the Token Metadata program when thawing
+    pub nft_edition: AccountInfo<'info>,
+
+    /// the valut account
+    #[account(
+        mut,
+        seeds = [rewarder.collection.as_bytes(),&id().to_bytes(), VAULT_PREFIX,&owner.key().to_bytes()],
+        bump = vault_account.bump,
+    )]
+    pub vault_account: Account<'info, VaultAccount>,
+
+    /// The receipt recording the stake of the NFT, closed to return the rent to the owner
+    #[account(
+        mut,
+        close = owner,
+        has_one = owner @ StakingError::InvalidOwnerForStakeReceipt,
+        seeds = [rewarder.collection.as_bytes(), &id().to_bytes(), RECEIPT_PREFIX, &rewarder.key().to_bytes(), &nft_mint.key().to_bytes()],
+        bump = stake_receipt.bump,
+    )]
+    pub stake_receipt: Box<Account<'info, StakeReceipt>>,
+
+    /// The legacy token program or Token-2022
+    /// CHECK: either token program, checked by the constraint
+    #[account(constraint = token_interface::is_token_program(token_program.key) @ StakingError::InvalidTokenProgram)]
+    pub token_program: AccountInfo<'info>,
+    pub token_metadata_program: Program<'info, MetaplexTokenMetadata>,
+
+    /// The Mint of the rewarded token, the early unstake fee is burnt from the owner for mint backed rewarders
+    /// CHECK: the reward mint of the rewarder
+    #[account(
+        mut,
+        address = rewarder.reward_mint @ StakingError::InvalidRewardMint,
+    )]
+    pub reward_mint: AccountInfo<'info>,
+
+    /// The token account for the reward mint the owner pays the early unstake fee from
+    /// CHECK: unpacked and checked when a fee is paid
+    #[account(mut)]
+    pub reward_account: AccountInfo<'info>,
+
+    /// The token account holding the rewards, which receives the early unstake fee of treasury backed rewarders
+    /// CHECK: the treasury PDA created with the rewarder
+    #[account(
+        mut,
+        seeds = [rewarder.collection.as_bytes(), &id().to_bytes(), TREASURY_PREFIX, &rewarder.key().to_bytes()],
+        bump = rewarder.reward_treasury_bump,
     )]
+    pub reward_treasury: AccountInfo<'info>,
+
+    /// The token program of the reward mint
+    /// CHECK: either token program, checked by the constraint
+    #[account(constraint = token_interface::is_token_program(reward_token_program.key) @ StakingError::InvalidTokenProgram)]
+    pub reward_token_program: AccountInfo<'info>,
+
+    pub clock: Sysvar<'info, Clock>,
+}
+
+impl<'info> UnstakeNft<'info> {
+    pub fn return_nft_accounts(&self) -> ReturnNft<'_, 'info> {
+        ReturnNft {
+            owner: &self.owner,
+            rewarder: &self.rewarder,
+            stake_account: &self.stake_account,
+            nft_mint: &self.nft_mint,
+            nft_token_account: &self.nft_token_account,
+            nft_escrow: &self.nft_escrow,
+            nft_edition: &self.nft_edition,
+            token_program: &self.token_program,
+            token_metadata_program: &self.token_metadata_program,
+        }
+    }
+}
+
+#[derive(Accounts)]
+pub struct EmergencyUnstake<'info> {
+    /// The owner of the stake account
+    /// CHECK:` doc comment explaining why no checks through types are necessary.
+    #[account(mut, signer)]
+    pub owner: AccountInfo<'info>,
+
+    /// The rewarder account for the collection
+    /// CHECK:` doc comment explaining why no checks through types are necessary.
+    #[account(mut)]
     pub rewarder: Account<'info, NftStakeRewarder>,
 
-    /// The owner of the rewarder account
+    /// The stake account for the owner
+    #[account(
+        mut,
+        has_one = rewarder @ StakingError::InvalidRewarder,
+        has_one = owner @ StakingError::InvalidOwnerForStakeAccount,
+        seeds = [rewarder.collection.as_bytes(), &id().to_bytes(), ACCOUNT_PREFIX, &rewarder.key().to_bytes(), &owner.key().to_bytes()],
+        bump = stake_account.bump,
+    )]
+    pub stake_account: Account<'info, NftStakeAccount>,
+
+    /// The Mint of the NFT, under either token program
+    /// CHECK: unpacked in the handler
+    pub nft_mint: AccountInfo<'info>,
+
+    /// The token account from the owner
+    /// CHECK: unpacked and checked in the handler
+    #[account(
+        mut,
+        address = token_interface::get_associated_token_address(&owner.key(), &nft_mint.key(), token_program.key),
+    )]
+    pub nft_token_account: AccountInfo<'info>,
+
+    /// The escrow token account of the stake account, only used by escrow rewarders
+    #[account(
+        mut,
+        address = token_interface::get_associated_token_address(&stake_account.key(), &nft_mint.key(), token_program.key),
+    )]
+    /// CHECK:` doc comment explaining why no checks through types are necessary.
+    pub nft_escrow: AccountInfo<'info>,
+
+    /// The master edition of the NFT, only used by freeze rewarders
+    /// CHECK: verified by the Token Metadata program when thawing
+    pub nft_edition: AccountInfo<'info>,
+
+    /// the valut account
+    #[account(
+        mut,
+        seeds = [rewarder.collection.as_bytes(),&id().to_bytes(), VAULT_PREFIX,&owner.key().to_bytes()],
+        bump = vault_account.bump,
+    )]
+    pub vault_account: Account<'info, VaultAccount>,
+
+    /// The receipt recording the stake of the NFT, closed to return the rent to the owner
+    #[account(
+        mut,
+        close = owner,
+        has_one = owner @ StakingError::InvalidOwnerForStakeReceipt,
+        seeds = [rewarder.collection.as_bytes(), &id().to_bytes(), RECEIPT_PREFIX, &rewarder.key().to_bytes(), &nft_mint.key().to_bytes()],
+        bump = stake_receipt.bump,
+    )]
+    pub stake_receipt: Box<Account<'info, StakeReceipt>>,
+
+    /// The legacy token program or Token-2022
+    /// CHECK: either token program, checked by the constraint
+    #[account(constraint = token_interface::is_token_program(token_program.key) @ StakingError::InvalidTokenProgram)]
+    pub token_program: AccountInfo<'info>,
+    pub token_metadata_program: Program<'info, MetaplexTokenMetadata>,
+
+    pub clock: Sysvar<'info, Clock>,
+}
+
+impl<'info> EmergencyUnstake<'info> {
+    pub fn return_nft_accounts(&self) -> ReturnNft<'_, 'info> {
+        ReturnNft {
+            owner: &self.owner,
+            rewarder: &self.rewarder,
+            stake_account: &self.stake_account,
+            nft_mint: &self.nft_mint,
+            nft_token_account: &self.nft_token_account,
+            nft_escrow: &self.nft_escrow,
+            nft_edition: &self.nft_edition,
+            token_program: &self.token_program,
+            token_metadata_program: &self.token_metadata_program,
+        }
+    }
+}
+
+#[derive(Accounts)]
+pub struct RequestUnstake<'info> {
+    /// The owner of the stake account
+    /// CHECK:` doc comment explaining why no checks through types are necessary.
+    #[account(signer)]
+    pub owner: AccountInfo<'info>,
+
+    /// The rewarder account for the collection
+    #[account(mut)]
+    pub rewarder: Account<'info, NftStakeRewarder>,
+
+    /// The stake account for the owner
+    #[account(
+        mut,
+        has_one = rewarder @ StakingError::InvalidRewarder,
+        has_one = owner @ StakingError::InvalidOwnerForStakeAccount,
+        seeds = [rewarder.collection.as_bytes(), &id().to_bytes(), ACCOUNT_PREFIX, &rewarder.key().to_bytes(), &owner.key().to_bytes()],
+        bump = stake_account.bump,
+    )]
+    pub stake_account: Account<'info, NftStakeAccount>,
+
+    /// The Mint of the NFT, or the asset id of a compressed NFT
+    /// CHECK: only used to derive the stake receipt
+    pub nft_mint: AccountInfo<'info>,
+
+    /// The receipt recording the stake of the NFT
+    #[account(
+        has_one = owner @ StakingError::InvalidOwnerForStakeReceipt,
+        seeds = [rewarder.collection.as_bytes(), &id().to_bytes(), RECEIPT_PREFIX, &rewarder.key().to_bytes(), &nft_mint.key().to_bytes()],
+        bump = stake_receipt.bump,
+    )]
+    pub stake_receipt: Box<Account<'info, StakeReceipt>>,
+
+    /// The Mint of the rewarded token, the early unstake fee is burnt from the owner for mint backed rewarders
+    /// CHECK: the reward mint of the rewarder
+    #[account(
+        mut,
+        address = rewarder.reward_mint @ StakingError::InvalidRewardMint,
+    )]
+    pub reward_mint: AccountInfo<'info>,
+
+    /// The token account for the reward mint the owner pays the early unstake fee from
+    /// CHECK: unpacked and checked when a fee is paid
+    #[account(mut)]
+    pub reward_account: AccountInfo<'info>,
+
+    /// The token account holding the rewards, which receives the early unstake fee of treasury backed rewarders
+    /// CHECK: the treasury PDA created with the rewarder
+    #[account(
+        mut,
+        seeds = [rewarder.collection.as_bytes(), &id().to_bytes(), TREASURY_PREFIX, &rewarder.key().to_bytes()],
+        bump = rewarder.reward_treasury_bump,
+    )]
+    pub reward_treasury: AccountInfo<'info>,
+
+    /// The token program of the reward mint
+    /// CHECK: either token program, checked by the constraint
+    #[account(constraint = token_interface::is_token_program(reward_token_program.key) @ StakingError::InvalidTokenProgram)]
+    pub reward_token_program: AccountInfo<'info>,
+
+    pub clock: Sysvar<'info, Clock>,
+}
+
+#[derive(Accounts)]
+pub struct ExtendLock<'info> {
+    /// The owner of the stake account
+    /// CHECK:` doc comment explaining why no checks through types are necessary.
+    #[account(signer)]
+    pub owner: AccountInfo<'info>,
+
+    /// The rewarder account for the collection
+    #[account(mut)]
+    pub rewarder: Account<'info, NftStakeRewarder>,
+
+    /// The stake account for the owner
+    #[account(
+        mut,
+        has_one = rewarder @ StakingError::InvalidRewarder,
+        has_one = owner @ StakingError::InvalidOwnerForStakeAccount,
+        seeds = [rewarder.collection.as_bytes(), &id().to_bytes(), ACCOUNT_PREFIX, &rewarder.key().to_bytes(), &owner.key().to_bytes()],
+        bump = stake_account.bump,
+    )]
+    pub stake_account: Account<'info, NftStakeAccount>,
+
+    /// The Mint of the NFT, or the asset id of a compressed NFT
+    /// CHECK: only used to derive the stake receipt
+    pub nft_mint: AccountInfo<'info>,
+
+    /// The receipt recording the stake of the NFT, updated with the new lock
+    #[account(
+        mut,
+        has_one = owner @ StakingError::InvalidOwnerForStakeReceipt,
+        seeds = [rewarder.collection.as_bytes(), &id().to_bytes(), RECEIPT_PREFIX, &rewarder.key().to_bytes(), &nft_mint.key().to_bytes()],
+        bump = stake_receipt.bump,
+    )]
+    pub stake_receipt: Box<Account<'info, StakeReceipt>>,
+
+    pub clock: Sysvar<'info, Clock>,
+}
+
+#[derive(Accounts)]
+pub struct StakePnft<'info> {
+    /// The owner of the stake account
     /// CHECK:` doc comment explaining why no checks through types are necessary.
     #[account(mut, signer)]
-    pub authority: AccountInfo<'info>,
+    pub owner: AccountInfo<'info>,
+
+    /// The rewarder account for the collection
+    #[account(mut)]
+    pub rewarder: Box<Account<'info, NftStakeRewarder>>,
+
+    /// The stake account for the owner
+    #[account(
+        mut,
+        has_one = rewarder @ StakingError::InvalidRewarder,
+        has_one = owner @ StakingError::InvalidOwnerForStakeAccount,
+        seeds = [rewarder.collection.as_bytes(), &id().to_bytes(), ACCOUNT_PREFIX, &rewarder.key().to_bytes(), &owner.key().to_bytes()],
+        bump = stake_account.bump,
+    )]
+    pub stake_account: Box<Account<'info, NftStakeAccount>>,
+
+    /// the valut account
+    #[account(
+        mut,
+        seeds = [rewarder.collection.as_bytes(),&id().to_bytes(), VAULT_PREFIX,&owner.key().to_bytes()],
+        bump = vault_account.bump,
+    )]
+    pub vault_account: Box<Account<'info, VaultAccount>>,
 
-    /// PDA used for minting rewards
+    /// The receipt recording the stake of the pNFT
     #[account(
-        seeds = [collection.as_bytes(), &id().to_bytes(), REWARDER_PREFIX, &rewarder.key().to_bytes()],
-        bump = reward_authority_bump,
+        init,
+        payer = owner,
+        space = StakeReceipt::LEN,
+        seeds = [rewarder.collection.as_bytes(), &id().to_bytes(), RECEIPT_PREFIX, &rewarder.key().to_bytes(), &nft_mint.key().to_bytes()],
+        bump,
     )]
-    /// CHECK:` doc comment explaining why no checks through types are necessary.
-    pub reward_authority: AccountInfo<'info>,
+    pub stake_receipt: Box<Account<'info, StakeReceipt>>,
 
-    /// The SPL Mint of the reward token. Must have the reward authority mint authority
+    /// The Mint of the pNFT
     #[account(
-        constraint = reward_mint.mint_authority.contains(&reward_authority.key()) @ StakingError::RewarderNotMintAuthority
+        constraint = nft_mint.supply == 1 @ StakingError::InvalidNFTMintSupply,
     )]
-    pub reward_mint: Account<'info, Mint>,
-
-    pub system_program: Program<'info, System>,
-    pub rent: Sysvar<'info, Rent>,
-}
+    pub nft_mint: Box<Account<'info, Mint>>,
 
-#[derive(Accounts)]
-pub struct UpdateRewardRate<'info> {
-    /// The new rewarder account to updtae
+    /// The token account from the owner
     #[account(
         mut,
-        has_one = authority @ StakingError::InvalidRewarderAuthority,
+        has_one = owner @ StakingError::InvalidNFTOwner,
+        constraint = nft_token_account.mint == nft_mint.key() @ StakingError::InvalidNFTAccountMint,
+        constraint = nft_token_account.amount == 1 @ StakingError::NFTAccountEmpty,
     )]
-    pub rewarder: Account<'info, NftStakeRewarder>,
+    pub nft_token_account: Box<Account<'info, TokenAccount>>,
 
-    /// The owner of the rewarder account
-    /// CHECK:` doc comment explaining why no checks through types are necessary.
-    #[account(signer)]
-    pub authority: AccountInfo<'info>,
+    /// The metadata of the pNFT, checked against the rewarder when it enforces metadata
+    /// CHECK: verified by the Token Metadata program
+    #[account(mut)]
+    pub nft_metadata: AccountInfo<'info>,
+
+    /// The master edition of the pNFT
+    /// CHECK: verified by the Token Metadata program
+    pub nft_edition: AccountInfo<'info>,
+
+    /// The token record of the pNFT in the owner's token account
+    /// CHECK: verified by the Token Metadata program
+    #[account(mut)]
+    pub nft_token_record: AccountInfo<'info>,
+
+    /// The rule set program, or the Token Metadata program when the pNFT has no rule set
+    /// CHECK: verified by the Token Metadata program
+    pub authorization_rules_program: AccountInfo<'info>,
+
+    /// The rule set of the pNFT, or the Token Metadata program when it has none
+    /// CHECK: verified by the Token Metadata program
+    pub authorization_rules: AccountInfo<'info>,
+
+    /// CHECK: the instructions sysvar
+    #[account(address = sysvar::instructions::ID)]
+    pub sysvar_instructions: AccountInfo<'info>,
+
+    pub token_program: Program<'info, Token>,
+    pub token_metadata_program: Program<'info, MetaplexTokenMetadata>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+    pub clock: Sysvar<'info, Clock>,
 }
 
 #[derive(Accounts)]
-#[instruction(bump: u8)]
-pub struct InitializeStakeAccount<'info> {
+pub struct UnstakePnft<'info> {
     /// The owner of the stake account
     /// CHECK:` doc comment explaining why no checks through types are necessary.
     #[account(mut, signer)]
     pub owner: AccountInfo<'info>,
 
-    /// The new stake account to initialize
+    /// The rewarder account for the collection
+    #[account(mut)]
+    pub rewarder: Box<Account<'info, NftStakeRewarder>>,
+
+    /// The stake account for the owner
     #[account(
-        init,
-        payer = owner,
-        space = 9000,
+        mut,
+        has_one = rewarder @ StakingError::InvalidRewarder,
+        has_one = owner @ StakingError::InvalidOwnerForStakeAccount,
         seeds = [rewarder.collection.as_bytes(), &id().to_bytes(), ACCOUNT_PREFIX, &rewarder.key().to_bytes(), &owner.key().to_bytes()],
-        bump
+        bump = stake_account.bump,
     )]
-    pub stake_account: Account<'info, NftStakeAccount>,
+    pub stake_account: Box<Account<'info, NftStakeAccount>>,
 
-    /// The rewarder associated with this stake account
-    pub rewarder: Account<'info, NftStakeRewarder>,
+    /// the valut account
+    #[account(
+        mut,
+        seeds = [rewarder.collection.as_bytes(),&id().to_bytes(), VAULT_PREFIX,&owner.key().to_bytes()],
+        bump = vault_account.bump,
+    )]
+    pub vault_account: Box<Account<'info, VaultAccount>>,
 
-    pub system_program: Program<'info, System>,
-    pub rent: Sysvar<'info, Rent>,
-}
+    /// The receipt recording the stake of the pNFT, closed to return the rent to the owner
+    #[account(
+        mut,
+        close = owner,
+        has_one = owner @ StakingError::InvalidOwnerForStakeReceipt,
+        seeds = [rewarder.collection.as_bytes(), &id().to_bytes(), RECEIPT_PREFIX, &rewarder.key().to_bytes(), &nft_mint.key().to_bytes()],
+        bump = stake_receipt.bump,
+    )]
+    pub stake_receipt: Box<Account<'info, StakeReceipt>>,
 
-#[derive(Accounts)]
-#[instruction(bump: u8)]
-pub struct InitializeVaultAccount<'info> {
-  #[account(mut, signer)]
-  /// CHECK:` doc comment explaining why no checks through types are necessary.
-  pub owner: AccountInfo<'info>,
+    /// The Mint of the pNFT
+    pub nft_mint: Box<Account<'info, Mint>>,
 
-  #[account(
-    init,
-    space = 10240,
-    payer = owner,
-    seeds = [rewarder.collection.as_bytes(), &id().to_bytes(), VAULT_PREFIX,&owner.key().to_bytes()],
-    bump
+    /// The token account from the owner
+    #[account(
+        mut,
+        has_one = owner @ StakingError::InvalidNFTOwner,
+        constraint = nft_token_account.mint == nft_mint.key() @ StakingError::InvalidNFTAccountMint,
     )]
-  pub vault_account: Account<'info, VaultAccount>,
+    pub nft_token_account: Box<Account<'info, TokenAccount>>,
+
+    /// The metadata of the pNFT
+    /// CHECK: verified by the Token Metadata program
+    #[account(mut)]
+    pub nft_metadata: AccountInfo<'info>,
 
-  #[account(mut)]
-  pub reward_mint: Account<'info, Mint>,
+    /// The master edition of the pNFT
+    /// CHECK: verified by the Token Metadata program
+    pub nft_edition: AccountInfo<'info>,
 
-  pub rewarder: Account<'info, NftStakeRewarder>,
+    /// The token record of the pNFT in the owner's token account
+    /// CHECK: verified by the Token Metadata program
+    #[account(mut)]
+    pub nft_token_record: AccountInfo<'info>,
 
+    /// The rule set program, or the Token Metadata program when the pNFT has no rule set
+    /// CHECK: verified by the Token Metadata program
+    pub authorization_rules_program: AccountInfo<'info>,
 
-  pub system_program: Program <'info, System>,
-  pub rent: Sysvar<'info, Rent>,
+    /// The rule set of the pNFT, or the Token Metadata program when it has none
+    /// CHECK: verified by the Token Metadata program
+    pub authorization_rules: AccountInfo<'info>,
+
+    /// CHECK: the instructions sysvar
+    #[account(address = sysvar::instructions::ID)]
+    pub sysvar_instructions: AccountInfo<'info>,
+
+    pub token_program: Program<'info, Token>,
+    pub token_metadata_program: Program<'info, MetaplexTokenMetadata>,
+    pub system_program: Program<'info, System>,
+
+    /// The Mint of the rewarded token, the early unstake fee is burnt from the owner for mint backed rewarders
+    /// CHECK: the reward mint of the rewarder
+    #[account(
+        mut,
+        address = rewarder.reward_mint @ StakingError::InvalidRewardMint,
+    )]
+    pub reward_mint: AccountInfo<'info>,
+
+    /// The token account for the reward mint the owner pays the early unstake fee from
+    /// CHECK: unpacked and checked when a fee is paid
+    #[account(mut)]
+    pub reward_account: AccountInfo<'info>,
+
+    /// The token account holding the rewards, which receives the early unstake fee of treasury backed rewarders
+    /// CHECK: the treasury PDA created with the rewarder
+    #[account(
+        mut,
+        seeds = [rewarder.collection.as_bytes(), &id().to_bytes(), TREASURY_PREFIX, &rewarder.key().to_bytes()],
+        bump = rewarder.reward_treasury_bump,
+    )]
+    pub reward_treasury: AccountInfo<'info>,
+
+    /// The token program of the reward mint
+    /// CHECK: either token program, checked by the constraint
+    #[account(constraint = token_interface::is_token_program(reward_token_program.key) @ StakingError::InvalidTokenProgram)]
+    pub reward_token_program: AccountInfo<'info>,
+
+    pub clock: Sysvar<'info, Clock>,
 }
 
+impl<'info> UnstakePnft<'info> {
+    pub fn return_pnft_accounts(&self) -> ReturnPnft<'_, 'info> {
+        ReturnPnft {
+            owner: &self.owner,
+            rewarder: &self.rewarder,
+            stake_account: &self.stake_account,
+            nft_mint: &self.nft_mint,
+            nft_token_account: &self.nft_token_account,
+            nft_metadata: &self.nft_metadata,
+            nft_edition: &self.nft_edition,
+            nft_token_record: &self.nft_token_record,
+            authorization_rules_program: &self.authorization_rules_program,
+            authorization_rules: &self.authorization_rules,
+            sysvar_instructions: &self.sysvar_instructions,
+            token_program: &self.token_program,
+            token_metadata_program: &self.token_metadata_program,
+            system_program: &self.system_program,
+        }
+    }
+}
 
 #[derive(Accounts)]
-// #[instruction(_vault_bump: u8)]
-pub struct StakeNft<'info> {
+pub struct EmergencyUnstakePnft<'info> {
     /// The owner of the stake account
     /// CHECK:` doc comment explaining why no checks through types are necessary.
     #[account(mut, signer)]
@@ -525,14 +2728,6 @@ pub struct StakeNft<'info> {
     #[account(mut)]
     pub rewarder: Box<Account<'info, NftStakeRewarder>>,
 
-    /// PDA that has the authority to mint reward tokens
-    #[account(
-        seeds = [rewarder.collection.as_bytes(), &id().to_bytes(), REWARDER_PREFIX, &rewarder.key().to_bytes()],
-        bump = rewarder.reward_authority_bump,
-    )]
-    /// CHECK:` doc comment explaining why no checks through types are necessary.
-    pub reward_authority: AccountInfo<'info>,
-
     /// The stake account for the owner
     #[account(
         mut,
@@ -541,35 +2736,27 @@ pub struct StakeNft<'info> {
         seeds = [rewarder.collection.as_bytes(), &id().to_bytes(), ACCOUNT_PREFIX, &rewarder.key().to_bytes(), &owner.key().to_bytes()],
         bump = stake_account.bump,
     )]
-    pub stake_account: Account<'info, NftStakeAccount>,
+    pub stake_account: Box<Account<'info, NftStakeAccount>>,
 
-    /// The Mint of the rewarded token
+    /// the valut account
     #[account(
         mut,
-        address = rewarder.reward_mint @ StakingError::InvalidRewardMint,
+        seeds = [rewarder.collection.as_bytes(),&id().to_bytes(), VAULT_PREFIX,&owner.key().to_bytes()],
+        bump = vault_account.bump,
     )]
-    pub reward_mint: Box<Account<'info, Mint>>,
+    pub vault_account: Box<Account<'info, VaultAccount>>,
 
-    /// The token account from the owner
+    /// The receipt recording the stake of the pNFT, closed to return the rent to the owner
     #[account(
         mut,
-        has_one = owner @ StakingError::InvalidOwnerForRewardToken,
-        constraint = reward_token_account.mint == rewarder.reward_mint @ StakingError::InvalidRewardTokenAccount,
-    )]
-    pub reward_token_account: Account<'info, TokenAccount>,
-
-    /// The stake account for the owner
-    #[account(
-    mut,
-    seeds = [rewarder.collection.as_bytes(),&id().to_bytes(), VAULT_PREFIX,&owner.key().to_bytes()],
-    bump = vault_account.bump,
+        close = owner,
+        has_one = owner @ StakingError::InvalidOwnerForStakeReceipt,
+        seeds = [rewarder.collection.as_bytes(), &id().to_bytes(), RECEIPT_PREFIX, &rewarder.key().to_bytes(), &nft_mint.key().to_bytes()],
+        bump = stake_receipt.bump,
     )]
-    pub vault_account: Account<'info, VaultAccount>,
+    pub stake_receipt: Box<Account<'info, StakeReceipt>>,
 
-    /// The Mint of the NFT
-    #[account(
-        constraint = nft_mint.supply == 1 @ StakingError::InvalidNFTMintSupply,
-    )]
+    /// The Mint of the pNFT
     pub nft_mint: Box<Account<'info, Mint>>,
 
     /// The token account from the owner
@@ -577,35 +2764,135 @@ pub struct StakeNft<'info> {
         mut,
         has_one = owner @ StakingError::InvalidNFTOwner,
         constraint = nft_token_account.mint == nft_mint.key() @ StakingError::InvalidNFTAccountMint,
-        constraint = nft_token_account.amount == 1 @ StakingError::NFTAccountEmpty,
     )]
-    pub nft_token_account: Account<'info, TokenAccount>,
+    pub nft_token_account: Box<Account<'info, TokenAccount>>,
+
+    /// The metadata of the pNFT
+    /// CHECK: verified by the Token Metadata program
+    #[account(mut)]
+    pub nft_metadata: AccountInfo<'info>,
+
+    /// The master edition of the pNFT
+    /// CHECK: verified by the Token Metadata program
+    pub nft_edition: AccountInfo<'info>,
+
+    /// The token record of the pNFT in the owner's token account
+    /// CHECK: verified by the Token Metadata program
+    #[account(mut)]
+    pub nft_token_record: AccountInfo<'info>,
+
+    /// The rule set program, or the Token Metadata program when the pNFT has no rule set
+    /// CHECK: verified by the Token Metadata program
+    pub authorization_rules_program: AccountInfo<'info>,
+
+    /// The rule set of the pNFT, or the Token Metadata program when it has none
+    /// CHECK: verified by the Token Metadata program
+    pub authorization_rules: AccountInfo<'info>,
+
+    /// CHECK: the instructions sysvar
+    #[account(address = sysvar::instructions::ID)]
+    pub sysvar_instructions: AccountInfo<'info>,
 
     pub token_program: Program<'info, Token>,
+    pub token_metadata_program: Program<'info, MetaplexTokenMetadata>,
     pub system_program: Program<'info, System>,
-    pub rent: Sysvar<'info, Rent>,
+
     pub clock: Sysvar<'info, Clock>,
 }
 
+impl<'info> EmergencyUnstakePnft<'info> {
+    pub fn return_pnft_accounts(&self) -> ReturnPnft<'_, 'info> {
+        ReturnPnft {
+            owner: &self.owner,
+            rewarder: &self.rewarder,
+            stake_account: &self.stake_account,
+            nft_mint: &self.nft_mint,
+            nft_token_account: &self.nft_token_account,
+            nft_metadata: &self.nft_metadata,
+            nft_edition: &self.nft_edition,
+            nft_token_record: &self.nft_token_record,
+            authorization_rules_program: &self.authorization_rules_program,
+            authorization_rules: &self.authorization_rules,
+            sysvar_instructions: &self.sysvar_instructions,
+            token_program: &self.token_program,
+            token_metadata_program: &self.token_metadata_program,
+            system_program: &self.system_program,
+        }
+    }
+}
+
 #[derive(Accounts)]
-pub struct UnstakeNft<'info> {
+#[instruction(locking_period: i64, asset_id: Pubkey)]
+pub struct StakeCnft<'info> {
     /// The owner of the stake account
     /// CHECK:` doc comment explaining why no checks through types are necessary.
     #[account(mut, signer)]
     pub owner: AccountInfo<'info>,
 
     /// The rewarder account for the collection
-    /// CHECK:` doc comment explaining why no checks through types are necessary.
     #[account(mut)]
-    pub rewarder: Account<'info, NftStakeRewarder>,
+    pub rewarder: Box<Account<'info, NftStakeRewarder>>,
 
-    /// PDA that has the authority to mint reward tokens
+    /// The stake account for the owner
     #[account(
-        seeds = [rewarder.collection.as_bytes(), &id().to_bytes(), REWARDER_PREFIX, &rewarder.key().to_bytes()],
-        bump = rewarder.reward_authority_bump,
+        mut,
+        has_one = rewarder @ StakingError::InvalidRewarder,
+        has_one = owner @ StakingError::InvalidOwnerForStakeAccount,
+        seeds = [rewarder.collection.as_bytes(), &id().to_bytes(), ACCOUNT_PREFIX, &rewarder.key().to_bytes(), &owner.key().to_bytes()],
+        bump = stake_account.bump,
+    )]
+    pub stake_account: Box<Account<'info, NftStakeAccount>>,
+
+    /// the valut account
+    #[account(
+        mut,
+        seeds = [rewarder.collection.as_bytes(),&id().to_bytes(), VAULT_PREFIX,&owner.key().to_bytes()],
+        bump = vault_account.bump,
+    )]
+    pub vault_account: Box<Account<'info, VaultAccount>>,
+
+    /// The receipt recording the stake of the cNFT, keyed by its asset id
+    #[account(
+        init,
+        payer = owner,
+        space = StakeReceipt::LEN,
+        seeds = [rewarder.collection.as_bytes(), &id().to_bytes(), RECEIPT_PREFIX, &rewarder.key().to_bytes(), asset_id.as_ref()],
+        bump,
     )]
+    pub stake_receipt: Box<Account<'info, StakeReceipt>>,
+
+    /// The config of the tree holding the cNFT
+    /// CHECK: verified by the Bubblegum program
+    pub tree_config: AccountInfo<'info>,
+
+    /// The tree holding the cNFT
+    /// CHECK: verified by the Bubblegum program
+    #[account(mut)]
+    pub merkle_tree: AccountInfo<'info>,
+
+    /// The current delegate of the cNFT, the owner when it has none
+    /// CHECK: verified against the leaf by the Bubblegum program
+    pub leaf_delegate: AccountInfo<'info>,
+
+    pub bubblegum_program: Program<'info, Bubblegum>,
+    pub compression_program: Program<'info, SplAccountCompression>,
+    pub log_wrapper: Program<'info, SplNoop>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+    pub clock: Sysvar<'info, Clock>,
+}
+
+#[derive(Accounts)]
+#[instruction(asset_id: Pubkey)]
+pub struct UnstakeCnft<'info> {
+    /// The owner of the stake account
     /// CHECK:` doc comment explaining why no checks through types are necessary.
-    pub reward_authority: AccountInfo<'info>,
+    #[account(mut, signer)]
+    pub owner: AccountInfo<'info>,
+
+    /// The rewarder account for the collection
+    #[account(mut)]
+    pub rewarder: Box<Account<'info, NftStakeRewarder>>,
 
     /// The stake account for the owner
     #[account(
@@ -615,37 +2902,107 @@ pub struct UnstakeNft<'info> {
         seeds = [rewarder.collection.as_bytes(), &id().to_bytes(), ACCOUNT_PREFIX, &rewarder.key().to_bytes(), &owner.key().to_bytes()],
         bump = stake_account.bump,
     )]
-    pub stake_account: Account<'info, NftStakeAccount>,
+    pub stake_account: Box<Account<'info, NftStakeAccount>>,
 
-    /// The Mint of the rewarded token
+    /// the valut account
     #[account(
         mut,
-        address = rewarder.reward_mint @ StakingError::InvalidRewardMint,
+        seeds = [rewarder.collection.as_bytes(),&id().to_bytes(), VAULT_PREFIX,&owner.key().to_bytes()],
+        bump = vault_account.bump,
     )]
-    pub reward_mint: Box<Account<'info, Mint>>,
+    pub vault_account: Box<Account<'info, VaultAccount>>,
 
-    /// The token account from the owner
+    /// The receipt recording the stake of the cNFT, closed to return the rent to the owner
     #[account(
         mut,
-        has_one = owner @ StakingError::InvalidOwnerForRewardToken,
-        constraint = reward_token_account.mint == rewarder.reward_mint @ StakingError::InvalidRewardTokenAccount,
+        close = owner,
+        has_one = owner @ StakingError::InvalidOwnerForStakeReceipt,
+        seeds = [rewarder.collection.as_bytes(), &id().to_bytes(), RECEIPT_PREFIX, &rewarder.key().to_bytes(), asset_id.as_ref()],
+        bump = stake_receipt.bump,
     )]
-    pub reward_token_account: Account<'info, TokenAccount>,
+    pub stake_receipt: Box<Account<'info, StakeReceipt>>,
+
+    /// The config of the tree holding the cNFT
+    /// CHECK: verified by the Bubblegum program
+    pub tree_config: AccountInfo<'info>,
+
+    /// The tree holding the cNFT
+    /// CHECK: verified by the Bubblegum program
+    #[account(mut)]
+    pub merkle_tree: AccountInfo<'info>,
+
+    pub bubblegum_program: Program<'info, Bubblegum>,
+    pub compression_program: Program<'info, SplAccountCompression>,
+    pub log_wrapper: Program<'info, SplNoop>,
+    pub system_program: Program<'info, System>,
 
-    /// The Mint of the NFT
+    /// The Mint of the rewarded token, the early unstake fee is burnt from the owner for mint backed rewarders
+    /// CHECK: the reward mint of the rewarder
     #[account(
-        constraint = nft_mint.supply == 1 @ StakingError::InvalidNFTMintSupply,
+        mut,
+        address = rewarder.reward_mint @ StakingError::InvalidRewardMint,
     )]
-    pub nft_mint: Box<Account<'info, Mint>>,
+    pub reward_mint: AccountInfo<'info>,
 
-    /// The token account from the owner
+    /// The token account for the reward mint the owner pays the early unstake fee from
+    /// CHECK: unpacked and checked when a fee is paid
+    #[account(mut)]
+    pub reward_account: AccountInfo<'info>,
+
+    /// The token account holding the rewards, which receives the early unstake fee of treasury backed rewarders
+    /// CHECK: the treasury PDA created with the rewarder
     #[account(
         mut,
-        constraint = nft_token_account.owner == stake_account.key() @ StakingError::InvalidStakedNFTOwner,
-        constraint = nft_token_account.mint == nft_mint.key() @ StakingError::InvalidNFTAccountMint,
-        address = get_associated_token_address(&owner.key(), &nft_mint.key()),
+        seeds = [rewarder.collection.as_bytes(), &id().to_bytes(), TREASURY_PREFIX, &rewarder.key().to_bytes()],
+        bump = rewarder.reward_treasury_bump,
+    )]
+    pub reward_treasury: AccountInfo<'info>,
+
+    /// The token program of the reward mint
+    /// CHECK: either token program, checked by the constraint
+    #[account(constraint = token_interface::is_token_program(reward_token_program.key) @ StakingError::InvalidTokenProgram)]
+    pub reward_token_program: AccountInfo<'info>,
+
+    pub clock: Sysvar<'info, Clock>,
+}
+
+impl<'info> UnstakeCnft<'info> {
+    pub fn return_cnft_accounts(&self) -> ReturnCnft<'_, 'info> {
+        ReturnCnft {
+            owner: &self.owner,
+            rewarder: &self.rewarder,
+            stake_account: &self.stake_account,
+            tree_config: &self.tree_config,
+            merkle_tree: &self.merkle_tree,
+            bubblegum_program: &self.bubblegum_program,
+            compression_program: &self.compression_program,
+            log_wrapper: &self.log_wrapper,
+            system_program: &self.system_program,
+        }
+    }
+}
+
+#[derive(Accounts)]
+#[instruction(asset_id: Pubkey)]
+pub struct EmergencyUnstakeCnft<'info> {
+    /// The owner of the stake account
+    /// CHECK:` doc comment explaining why no checks through types are necessary.
+    #[account(mut, signer)]
+    pub owner: AccountInfo<'info>,
+
+    /// The rewarder account for the collection
+    #[account(mut)]
+    pub rewarder: Box<Account<'info, NftStakeRewarder>>,
+
+    /// The stake account for the owner
+    #[account(
+        mut,
+        has_one = rewarder @ StakingError::InvalidRewarder,
+        has_one = owner @ StakingError::InvalidOwnerForStakeAccount,
+        seeds = [rewarder.collection.as_bytes(), &id().to_bytes(), ACCOUNT_PREFIX, &rewarder.key().to_bytes(), &owner.key().to_bytes()],
+        bump = stake_account.bump,
     )]
-    pub nft_token_account: Account<'info, TokenAccount>,
+    pub stake_account: Box<Account<'info, NftStakeAccount>>,
 
     /// the valut account
     #[account(
@@ -653,13 +3010,51 @@ pub struct UnstakeNft<'info> {
         seeds = [rewarder.collection.as_bytes(),&id().to_bytes(), VAULT_PREFIX,&owner.key().to_bytes()],
         bump = vault_account.bump,
     )]
-    pub vault_account: Account<'info, VaultAccount>,
+    pub vault_account: Box<Account<'info, VaultAccount>>,
+
+    /// The receipt recording the stake of the cNFT, closed to return the rent to the owner
+    #[account(
+        mut,
+        close = owner,
+        has_one = owner @ StakingError::InvalidOwnerForStakeReceipt,
+        seeds = [rewarder.collection.as_bytes(), &id().to_bytes(), RECEIPT_PREFIX, &rewarder.key().to_bytes(), asset_id.as_ref()],
+        bump = stake_receipt.bump,
+    )]
+    pub stake_receipt: Box<Account<'info, StakeReceipt>>,
+
+    /// The config of the tree holding the cNFT
+    /// CHECK: verified by the Bubblegum program
+    pub tree_config: AccountInfo<'info>,
 
+    /// The tree holding the cNFT
+    /// CHECK: verified by the Bubblegum program
+    #[account(mut)]
+    pub merkle_tree: AccountInfo<'info>,
+
+    pub bubblegum_program: Program<'info, Bubblegum>,
+    pub compression_program: Program<'info, SplAccountCompression>,
+    pub log_wrapper: Program<'info, SplNoop>,
+    pub system_program: Program<'info, System>,
 
-    pub token_program: Program<'info, Token>,
     pub clock: Sysvar<'info, Clock>,
 }
 
+impl<'info> EmergencyUnstakeCnft<'info> {
+    pub fn return_cnft_accounts(&self) -> ReturnCnft<'_, 'info> {
+        ReturnCnft {
+            owner: &self.owner,
+            rewarder: &self.rewarder,
+            stake_account: &self.stake_account,
+            tree_config: &self.tree_config,
+            merkle_tree: &self.merkle_tree,
+            bubblegum_program: &self.bubblegum_program,
+            compression_program: &self.compression_program,
+            log_wrapper: &self.log_wrapper,
+            system_program: &self.system_program,
+        }
+    }
+}
+
 #[derive(Accounts)]
 pub struct Claim<'info> {
     /// The owner of the stake account
@@ -668,7 +3063,7 @@ pub struct Claim<'info> {
     pub owner: AccountInfo<'info>,
 
     /// The rewarder account for the collection
-    #[account()]
+    #[account(mut)]
     pub rewarder: Account<'info, NftStakeRewarder>,
 
     /// The stake account for the owner
@@ -682,19 +3077,26 @@ pub struct Claim<'info> {
     pub stake_account: Account<'info, NftStakeAccount>,
 
     /// The Mint of the rewarded token
+    /// CHECK: the reward mint of the rewarder
     #[account(
         mut,
         address = rewarder.reward_mint @ StakingError::InvalidRewardMint,
     )]
-    pub reward_mint: Account<'info, Mint>,
+    pub reward_mint: AccountInfo<'info>,
 
     /// The token account for the reward mint for the owner
+    /// CHECK: unpacked and checked by claim
+    #[account(mut)]
+    pub reward_account: AccountInfo<'info>,
+
+    /// The token account holding the rewards for treasury backed rewarders
+    /// CHECK: the treasury PDA created with the rewarder
     #[account(
         mut,
-        has_one = owner @ StakingError::InvalidOwnerForRewardToken,
-        constraint = reward_account.mint == rewarder.reward_mint @ StakingError::InvalidRewardTokenAccount,
+        seeds = [rewarder.collection.as_bytes(), &id().to_bytes(), TREASURY_PREFIX, &rewarder.key().to_bytes()],
+        bump = rewarder.reward_treasury_bump,
     )]
-    pub reward_account: Account<'info, TokenAccount>,
+    pub reward_treasury: AccountInfo<'info>,
 
     /// PDA that has the authority to mint reward tokens
     #[account(
@@ -704,7 +3106,10 @@ pub struct Claim<'info> {
     /// CHECK:` doc comment explaining why no checks through types are necessary.
     pub reward_authority: AccountInfo<'info>,
 
-    pub token_program: Program<'info, Token>,
+    /// The legacy token program or Token-2022
+    /// CHECK: either token program, checked by the constraint
+    #[account(constraint = token_interface::is_token_program(token_program.key) @ StakingError::InvalidTokenProgram)]
+    pub token_program: AccountInfo<'info>,
     pub clock: Sysvar<'info, Clock>,
 }
 
@@ -723,20 +3128,29 @@ pub fn check_metadata<'a, 'b, 'c, 'info>(
     );
 
     if metadata.key() != expected_address {
-        return Err(StakingError::InvalidMetadataAccountAddress.into());
+        return Err(StakingError::InvalidMetadataAccountAddress);
     }
 
     if metadata.update_authority != rewarder.allowed_update_authority {
-        return Err(StakingError::InvalidMetadataUpdateAuthority.into());
+        return Err(StakingError::InvalidMetadataUpdateAuthority);
     }
 
-    if !metadata.data.name.starts_with(&rewarder.collection) {
-        return Err(StakingError::InvalidMetadataCollectionPrefix.into());
+    if let Some(collection_mint) = rewarder.collection_mint {
+        // verifying an item of a sized collection sets the same field as an unsized one
+        let is_verified_member = metadata
+            .collection
+            .as_ref()
+            .is_some_and(|collection| collection.verified && collection.key == collection_mint);
+        if !is_verified_member {
+            return Err(StakingError::UnverifiedCollectionMembership);
+        }
+    } else if !metadata.data.name.starts_with(&rewarder.collection) {
+        return Err(StakingError::InvalidMetadataCollectionPrefix);
     }
 
     if let Some(creators) = &metadata.data.creators {
         if creators.len() != rewarder.creators.len() {
-            return Err(StakingError::InvalidMetadataCreators.into());
+            return Err(StakingError::InvalidMetadataCreators);
         }
 
         for creator in creators.iter() {
@@ -745,61 +3159,1231 @@ pub fn check_metadata<'a, 'b, 'c, 'info>(
                 .iter()
                 .find(|known_creator| known_creator == creator);
             if found_match.is_none() {
-                return Err(StakingError::InvalidMetadataCreators.into());
+                return Err(StakingError::InvalidMetadataCreators);
             }
         }
     } else {
-        return Err(StakingError::InvalidMetadataCreators.into());
+        return Err(StakingError::InvalidMetadataCreators);
+    }
+
+    Ok(())
+}
+
+/// Checks the metadata of a cnft against the rewarder, the leaf vouches for it through its `data_hash`.
+/// cNFTs have no update authority, so it is not checked.
+pub fn check_cnft_metadata(
+    metadata: &MetadataArgs,
+    leaf: &LeafArgs,
+    rewarder: &NftStakeRewarder,
+) -> std::result::Result<(), StakingError> {
+    let data_hash = anchor_bubblegum::hash_metadata(metadata).map_err(|_| StakingError::InvalidCnftMetadata)?;
+    if data_hash != leaf.data_hash {
+        return Err(StakingError::InvalidCnftMetadata);
+    }
+
+    if let Some(collection_mint) = rewarder.collection_mint {
+        let is_verified_member = metadata
+            .collection
+            .as_ref()
+            .is_some_and(|collection| collection.verified && collection.key == collection_mint);
+        if !is_verified_member {
+            return Err(StakingError::UnverifiedCollectionMembership);
+        }
+    } else if !metadata.name.starts_with(&rewarder.collection) {
+        return Err(StakingError::InvalidMetadataCollectionPrefix);
+    }
+
+    // the creators are hashed into the leaf on their own
+    if leaf.creator_hash != rewarder.creator_hash() {
+        return Err(StakingError::InvalidMetadataCreators);
+    }
+
+    Ok(())
+}
+
+pub fn check_boost_tiers(
+    lock_tiers: &[LockTier],
+    quantity_tiers: &[QuantityTier],
+) -> std::result::Result<(), StakingError> {
+    if lock_tiers.len() > MAX_LOCK_TIERS || quantity_tiers.len() > MAX_QUANTITY_TIERS {
+        return Err(StakingError::TooManyBoostTiers);
+    }
+
+    for (i, tier) in lock_tiers.iter().enumerate() {
+        if tier.locking_period < 0 || tier.locking_period > MAX_LOCKING_PERIOD {
+            return Err(StakingError::InvalidLockingPeriod);
+        }
+        if lock_tiers[..i].iter().any(|other| other.locking_period == tier.locking_period) {
+            return Err(StakingError::DuplicateBoostTier);
+        }
+    }
+
+    for (i, tier) in quantity_tiers.iter().enumerate() {
+        if quantity_tiers[..i].iter().any(|other| other.min_count == tier.min_count) {
+            return Err(StakingError::DuplicateBoostTier);
+        }
+    }
+
+    Ok(())
+}
+
+pub fn check_trait_boosts(trait_boosts: &[TraitBoost]) -> std::result::Result<(), StakingError> {
+    if trait_boosts.len() > MAX_TRAIT_BOOSTS {
+        return Err(StakingError::TooManyBoostTiers);
+    }
+
+    for (i, boost) in trait_boosts.iter().enumerate() {
+        let pattern = match &boost.rule {
+            TraitRule::Symbol { symbol } => Some(symbol),
+            TraitRule::NameSuffix { suffix } => Some(suffix),
+            TraitRule::SellerFeeBasisPoints { .. } => None,
+        };
+        if pattern.is_some_and(|pattern| pattern.is_empty() || pattern.len() > MAX_TRAIT_PATTERN_LEN) {
+            return Err(StakingError::InvalidTraitBoost);
+        }
+        if trait_boosts[..i].iter().any(|other| other.rule == boost.rule) {
+            return Err(StakingError::DuplicateBoostTier);
+        }
+    }
+
+    Ok(())
+}
+
+/// Checks the NFT being staked and the token account it is staked from
+pub fn check_nft_token_account(
+    nft_mint: &token_interface::Mint,
+    nft_mint_key: &Pubkey,
+    nft_token_account: &token_interface::TokenAccount,
+    owner: &Pubkey,
+) -> std::result::Result<(), StakingError> {
+    if nft_mint.supply != 1 {
+        return Err(StakingError::InvalidNFTMintSupply);
+    }
+    if nft_token_account.owner != *owner {
+        return Err(StakingError::InvalidNFTOwner);
+    }
+    if nft_token_account.mint != *nft_mint_key {
+        return Err(StakingError::InvalidNFTAccountMint);
+    }
+    if nft_token_account.amount != 1 {
+        return Err(StakingError::NFTAccountEmpty);
     }
+    Ok(())
+}
+
+/// Rejects NFT mints with Token-2022 extensions that stop the NFT from being held by the program
+pub fn check_nft_mint_extensions(extensions: &[u16]) -> std::result::Result<(), StakingError> {
+    use token_interface::extension::*;
+
+    // transfer fees could take the NFT itself, the others block or claw back the escrow
+    let unsupported = [
+        TRANSFER_FEE_CONFIG,
+        DEFAULT_ACCOUNT_STATE,
+        NON_TRANSFERABLE,
+        PERMANENT_DELEGATE,
+        TRANSFER_HOOK,
+        PAUSABLE,
+    ];
+    if extensions.iter().any(|extension| unsupported.contains(extension)) {
+        return Err(StakingError::UnsupportedMintExtension);
+    }
+    Ok(())
+}
 
+/// Rejects reward mints with Token-2022 extensions that stop rewards from being paid
+pub fn check_reward_mint_extensions(
+    extensions: &[u16],
+    reward_source: RewardSource,
+) -> std::result::Result<(), StakingError> {
+    use token_interface::extension::*;
+
+    // transfer hooks need accounts claim does not take, frozen or paused accounts can't receive rewards
+    let unsupported = [DEFAULT_ACCOUNT_STATE, TRANSFER_HOOK, PAUSABLE];
+    // a treasury can't pay out tokens that don't transfer or that a permanent delegate can take
+    let unsupported_by_treasury = [NON_TRANSFERABLE, PERMANENT_DELEGATE];
+
+    let rejected = extensions.iter().any(|extension| {
+        unsupported.contains(extension)
+            || (reward_source == RewardSource::Treasury && unsupported_by_treasury.contains(extension))
+    });
+    if rejected {
+        return Err(StakingError::UnsupportedMintExtension);
+    }
     Ok(())
 }
 
-pub fn get_metadata_account<'a, 'b>(
-    accounts: &'a [AccountInfo<'b>],
+pub fn get_metadata_account<'b>(
+    accounts: &[AccountInfo<'b>],
 ) -> std::result::Result<Account<'b, MetadataAccount>, StakingError> {
     let accounts_iter = &mut accounts.iter();
     let metadata_info =
         next_account_info(accounts_iter).or(Err(StakingError::MetadataAccountNotFound))?;
 
+    load_metadata_account(metadata_info)
+}
+
+pub fn load_metadata_account<'b>(
+    metadata_info: &AccountInfo<'b>,
+) -> std::result::Result<Account<'b, MetadataAccount>, StakingError> {
     if *metadata_info.owner != anchor_metaplex::ID {
         return Err(StakingError::MetadataAccountNotOwnedByCorrectProgram);
     }
 
-    Ok(Account::try_from_unchecked(&metadata_info)
-        .or(Err(StakingError::InvalidMetadataAccountData))?)
+    Account::try_from_unchecked(metadata_info).or(Err(StakingError::InvalidMetadataAccountData))
+}
+
+/// Reads an account of this program in a layout from before stake receipts
+pub fn load_legacy_account<T: AnchorDeserialize>(
+    account: &AccountInfo,
+    discriminator: [u8; 8],
+) -> std::result::Result<T, StakingError> {
+    if *account.owner != id() {
+        return Err(StakingError::InvalidLegacyAccount);
+    }
+    let data = account.try_borrow_data().or(Err(StakingError::InvalidLegacyAccount))?;
+    if data.len() < 8 || data[..8] != discriminator {
+        return Err(StakingError::InvalidLegacyAccount);
+    }
+    T::deserialize(&mut &data[8..]).or(Err(StakingError::InvalidLegacyAccount))
+}
+
+/// The mints among `mints` that are staked, `stake_receipts` holds the stake receipt address
+/// of every mint in the same order, whether the receipt exists or not
+pub fn get_staked_mints(
+    rewarder: &Account<NftStakeRewarder>,
+    mints: &[Pubkey],
+    stake_receipts: &[AccountInfo],
+) -> std::result::Result<Vec<Pubkey>, StakingError> {
+    if stake_receipts.len() != mints.len() {
+        return Err(StakingError::InvalidStakeReceiptAddress);
+    }
+    let mut staked_mints = vec![];
+    for (mint, stake_receipt) in mints.iter().zip(stake_receipts) {
+        let (address, _) = Pubkey::find_program_address(
+            &[rewarder.collection.as_bytes(), &id().to_bytes(), RECEIPT_PREFIX, &rewarder.key().to_bytes(), &mint.to_bytes()],
+            &id(),
+        );
+        if stake_receipt.key() != address {
+            return Err(StakingError::InvalidStakeReceiptAddress);
+        }
+        // closed receipts are handed back to the system program
+        if *stake_receipt.owner == id() && !stake_receipt.data_is_empty() {
+            staked_mints.push(*mint);
+        }
+    }
+    Ok(staked_mints)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use proptest::prelude::*;
+
+    fn test_rewarder(reward_rate: u64) -> NftStakeRewarder {
+        NftStakeRewarder {
+            reward_rate,
+            lock_tiers: vec![
+                LockTier { locking_period: 7, multiplier_bps: 12_500 },
+                LockTier { locking_period: 30, multiplier_bps: 15_000 },
+            ],
+            quantity_tiers: vec![
+                QuantityTier { min_count: 5, multiplier_bps: 12_500 },
+                QuantityTier { min_count: 10, multiplier_bps: 15_000 },
+                QuantityTier { min_count: 15, multiplier_bps: 17_500 },
+                QuantityTier { min_count: 20, multiplier_bps: 20_000 },
+            ],
+            ..Default::default()
+        }
+    }
+
+    fn test_stake_account(nfts_staked: Vec<NftStaked>) -> NftStakeAccount {
+        NftStakeAccount {
+            owner: Pubkey::default(),
+            rewarder: Pubkey::default(),
+            nfts_staked,
+            bump: 0,
+            last_claimed: 0,
+            claimed_reward: 0,
+            reward_weight: 0,
+            reward_weight_clock: 0,
+        }
+    }
+
+    fn test_vault_account() -> VaultAccount {
+        VaultAccount {
+            total_staked: 0,
+            reward_mint: Pubkey::default(),
+            bump: 0,
+        }
+    }
+
+    fn test_stake_receipt() -> StakeReceipt {
+        StakeReceipt {
+            owner: Pubkey::default(),
+            rewarder: Pubkey::default(),
+            nft_mint: Pubkey::default(),
+            start_staking: 0,
+            locking_period: 0,
+            bump: 0,
+            weight_bps: BPS_DENOMINATOR,
+        }
+    }
 
     #[test]
     pub fn test_reward_calculation() {
         let current_time = 3600_i64;
-        let reward_rate = 2400_u64;
-        let last_claimed = 0_i64;
+        let rewarder = test_rewarder(2400);
+        let last_accrued = 0_i64;
         let mut num_staked = 0;
-        let mut locking_period = 0_i64;
+        let mut multiplier_bps = rewarder.lock_multiplier_bps(0);
     
 
 
         // if num staked is 0 always return 0 rewards
-        let earned_rewared = calculate_reward(reward_rate, num_staked,locking_period, last_claimed, current_time);
+        let earned_rewared = calculate_reward(&rewarder, multiplier_bps, BPS_DENOMINATOR, num_staked, current_time - last_accrued).unwrap();
         assert_eq!(earned_rewared, 0);
 
         num_staked += 1;
-        let earned_rewared = calculate_reward(reward_rate, num_staked,locking_period, last_claimed, current_time);
+        let earned_rewared = calculate_reward(&rewarder, multiplier_bps, BPS_DENOMINATOR, num_staked, current_time - last_accrued).unwrap();
         assert_eq!(earned_rewared, 100);
 
-        locking_period = 7;
-        let earned_rewared = calculate_reward(reward_rate,num_staked,locking_period, last_claimed, current_time);
+        multiplier_bps = rewarder.lock_multiplier_bps(7);
+        let earned_rewared = calculate_reward(&rewarder, multiplier_bps, BPS_DENOMINATOR, num_staked, current_time - last_accrued).unwrap();
         assert_eq!(earned_rewared, 125);
 
-        // //twice the number staked recieves twice the reward
+        // staking more nfts together boosts the reward of each of them
         num_staked += 9;
         
-        let earned_rewared = calculate_reward(reward_rate, num_staked,locking_period, last_claimed, current_time);
-        assert_eq!(earned_rewared, 1875);
+        let earned_rewared = calculate_reward(&rewarder, multiplier_bps, BPS_DENOMINATOR, num_staked, current_time - last_accrued).unwrap();
+        assert_eq!(earned_rewared, 187);
+    }
+
+    #[test]
+    pub fn test_settle_rewards_per_nft() {
+        let mut rewarder = test_rewarder(2400);
+        let mut stake_account = test_stake_account(vec![
+            NftStaked {
+                nft_mint: Pubkey::new_unique(),
+                locking_period: 0,
+                multiplier_bps: BPS_DENOMINATOR,
+                weight_bps: BPS_DENOMINATOR,
+                last_accrued: 0,
+                accrual_clock: 0,
+                accrued: 0,
+                accrued_in_lock: 0,
+                lock_boost_accrued_in_lock: 0,
+                unbonding_until: None,
+            },
+            NftStaked {
+                nft_mint: Pubkey::new_unique(),
+                locking_period: 7,
+                multiplier_bps: rewarder.lock_multiplier_bps(7),
+                weight_bps: BPS_DENOMINATOR,
+                last_accrued: 1800,
+                accrual_clock: 1800,
+                accrued: 10,
+                accrued_in_lock: 10,
+                lock_boost_accrued_in_lock: 2,
+                unbonding_until: None,
+            },
+        ]);
+        let nft_mints: Vec<Pubkey> = stake_account
+            .nfts_staked
+            .iter()
+            .map(|nft_staked| nft_staked.nft_mint)
+            .collect();
+        rewarder.add_whitelist_addresses(&nft_mints, 0);
+
+        settle_rewards(&mut rewarder, &mut stake_account, 3600).unwrap();
+        assert_eq!(stake_account.nfts_staked[0].accrued, 100);
+        assert_eq!(stake_account.nfts_staked[1].accrued, 10 + 62);
+        assert!(stake_account.nfts_staked.iter().all(|nft_staked| nft_staked.last_accrued == 3600));
+        assert_eq!(stake_account.claimed_reward, 162);
+        assert_eq!(rewarder.total_outstanding_rewards, 162);
+
+        // settling again at the same time accrues nothing new
+        settle_rewards(&mut rewarder, &mut stake_account, 3600).unwrap();
+        assert_eq!(stake_account.claimed_reward, 162);
+
+        // a mint removed from the whitelist stops accruing
+        rewarder.remove_whitelist_addresses(&nft_mints[..1], &nft_mints, 3600).unwrap();
+        assert_eq!(rewarder.total_whitelist_address, 1);
+        settle_rewards(&mut rewarder, &mut stake_account, 5400).unwrap();
+        assert_eq!(stake_account.nfts_staked[0].accrued, 100);
+        assert_eq!(stake_account.nfts_staked[1].accrued, 10 + 62 + 62);
+        assert_eq!(stake_account.claimed_reward, 162 + 62);
+
+        // a removed mint keeps what it accrued up to its removal, even when it was not settled in between
+        rewarder.remove_whitelist_addresses(&nft_mints[1..], &nft_mints, 7200).unwrap();
+        settle_rewards(&mut rewarder, &mut stake_account, 9000).unwrap();
+        assert_eq!(stake_account.nfts_staked[1].accrued, 10 + 62 + 62 + 62);
+        assert_eq!(stake_account.claimed_reward, 162 + 62 + 62);
+        // removed mints still count towards the outstanding liability, as they may be whitelisted again
+        assert_eq!(stake_account.reward_weight, 10_000 + 12_500);
+
+        // whitelisted again, nothing accrues for the time the mints were removed, even when not settled in between
+        rewarder.add_whitelist_addresses(&nft_mints, 10_800);
+        assert_eq!(rewarder.whitelist_removals.len(), 2);
+        settle_rewards(&mut rewarder, &mut stake_account, 12_600).unwrap();
+        assert_eq!(stake_account.nfts_staked[0].accrued, 100 + 50);
+        assert_eq!(stake_account.nfts_staked[1].accrued, 10 + 62 + 62 + 62 + 62);
+        assert_eq!(stake_account.claimed_reward, 162 + 62 + 62 + 50 + 62);
+        // the removals are settled
+        assert!(rewarder.whitelist_removals.is_empty());
+    }
+
+    #[test]
+    pub fn test_emission_limits() {
+        let mut rewarder = test_rewarder(2400);
+        rewarder.emission_end_ts = Some(1800);
+
+        // accrual stops at the end of the emission
+        assert_eq!(rewarder.accrual_clock_at(3600), 1800);
+        assert_eq!(rewarder.accrual_clock_at(7200), 1800);
+        let nft_staked = NftStaked {
+            multiplier_bps: BPS_DENOMINATOR,
+            weight_bps: BPS_DENOMINATOR,
+            ..Default::default()
+        };
+        rewarder.whitelist_addresses = vec![Pubkey::default()];
+        let mut stake_account = test_stake_account(vec![nft_staked.clone()]);
+        settle_rewards(&mut rewarder, &mut stake_account, 3600).unwrap();
+        assert_eq!(stake_account.claimed_reward, 50);
+        settle_rewards(&mut rewarder, &mut stake_account, 7200).unwrap();
+        assert_eq!(stake_account.claimed_reward, 50);
+
+        // a pause past the end of the emission takes nothing from what accrued before it
+        let mut rewarder = test_rewarder(24 * 3600);
+        rewarder.emission_end_ts = Some(100);
+        rewarder.whitelist_addresses = vec![Pubkey::default()];
+        let mut stake_account = test_stake_account(vec![nft_staked]);
+        rewarder.set_paused(PauseFlags { accrual: true, ..Default::default() }, 200);
+        rewarder.set_paused(PauseFlags::default(), 300);
+        settle_rewards(&mut rewarder, &mut stake_account, 400).unwrap();
+        assert_eq!(stake_account.claimed_reward, 100);
+
+        assert_eq!(rewarder.remaining_emission(), None);
+        rewarder.max_total_emission = Some(1000);
+        rewarder.total_emitted = 400;
+        assert_eq!(rewarder.remaining_emission(), Some(600));
+        rewarder.total_emitted = 1200;
+        assert_eq!(rewarder.remaining_emission(), Some(0));
+    }
+
+    #[test]
+    pub fn test_reward_overflow() {
+        let rewarder = test_rewarder(u64::MAX);
+        assert!(matches!(
+            calculate_reward(&rewarder, 15_000, BPS_DENOMINATOR, 20, i64::MAX),
+            Err(StakingError::MathOverflow)
+        ));
+        assert_eq!(calculate_reward(&rewarder, BPS_DENOMINATOR, BPS_DENOMINATOR, 1, i64::MIN).unwrap(), 0);
+
+        let nft_staked = NftStaked {
+            multiplier_bps: BPS_DENOMINATOR,
+            weight_bps: BPS_DENOMINATOR,
+            ..Default::default()
+        };
+        let mut stake_account = test_stake_account(vec![nft_staked; 2]);
+        stake_account.claimed_reward = u64::MAX;
+        let mut rewarder = test_rewarder(24 * 3600);
+        rewarder.whitelist_addresses = vec![Pubkey::default()];
+        assert!(matches!(
+            settle_rewards(&mut rewarder, &mut stake_account, 1),
+            Err(StakingError::MathOverflow)
+        ));
+    }
+
+    proptest! {
+        #[test]
+        fn prop_reward_never_panics(
+            reward_rate in any::<u64>(),
+            multiplier_bps in any::<u32>(),
+            num_staked in any::<u16>(),
+            elapsed_time in any::<i64>(),
+        ) {
+            let rewarder = test_rewarder(reward_rate);
+            match calculate_reward(&rewarder, multiplier_bps as u64, BPS_DENOMINATOR, num_staked, elapsed_time) {
+                Ok(_) | Err(StakingError::MathOverflow) => {}
+                Err(err) => prop_assert!(false, "unexpected error {:?}", err),
+            }
+        }
+
+        #[test]
+        fn prop_reward_matches_exact_formula(
+            reward_rate in 0_u64..=1_000_000_000_000,
+            num_staked in 1_u16..=100,
+            elapsed_time in 0_i64..=10 * 365 * 24 * 3600,
+        ) {
+            let rewarder = test_rewarder(reward_rate);
+            let expected = reward_rate as u128
+                * elapsed_time as u128
+                * rewarder.quantity_multiplier_bps(num_staked) as u128
+                / (24 * 3600 * BPS_DENOMINATOR as u128);
+            prop_assert_eq!(
+                calculate_reward(&rewarder, BPS_DENOMINATOR, BPS_DENOMINATOR, num_staked, elapsed_time).unwrap() as u128,
+                expected
+            );
+        }
+
+        #[test]
+        fn prop_reward_grows_with_time(
+            reward_rate in 0_u64..=1_000_000_000_000,
+            num_staked in 1_u16..=100,
+            elapsed_time in 0_i64..=10 * 365 * 24 * 3600,
+            extra_time in 0_i64..=365 * 24 * 3600,
+        ) {
+            let rewarder = test_rewarder(reward_rate);
+            let multiplier_bps = rewarder.lock_multiplier_bps(30);
+            let earlier = calculate_reward(&rewarder, multiplier_bps, BPS_DENOMINATOR, num_staked, elapsed_time).unwrap();
+            let later = calculate_reward(&rewarder, multiplier_bps, BPS_DENOMINATOR, num_staked, elapsed_time + extra_time).unwrap();
+            prop_assert!(later >= earlier);
+        }
+    }
+
+    #[test]
+    pub fn test_stake_receipt_lock() {
+        let stake_receipt = StakeReceipt {
+            start_staking: 1000,
+            locking_period: 7,
+            ..test_stake_receipt()
+        };
+        assert!(stake_receipt.is_locked(1000));
+        assert!(stake_receipt.is_locked(1007));
+        assert!(!stake_receipt.is_locked(1008));
+
+        // even a stake without a lock is held for the second it was staked in
+        let unlocked_receipt = StakeReceipt {
+            locking_period: 0,
+            ..stake_receipt
+        };
+        assert!(unlocked_receipt.is_locked(1000));
+        assert!(!unlocked_receipt.is_locked(1001));
+    }
+
+    #[test]
+    pub fn test_record_and_release_stake() {
+        let nft_mint = Pubkey::new_unique();
+        let mut rewarder = test_rewarder(100);
+        rewarder.whitelist_addresses = vec![nft_mint];
+        let mut stake_account = test_stake_account(vec![]);
+        let mut vault_account = test_vault_account();
+        let mut stake_receipt = test_stake_receipt();
+
+        assert!(matches!(
+            record_stake(&mut rewarder, &mut stake_account, &mut vault_account, &mut stake_receipt, nft_mint, 8, 1000),
+            Err(StakingError::InvalidLockingPeriod)
+        ));
+
+        record_stake(&mut rewarder, &mut stake_account, &mut vault_account, &mut stake_receipt, nft_mint, 7, 1000).unwrap();
+        assert_eq!(vault_account.total_staked, 1);
+        assert_eq!(stake_receipt.nft_mint, nft_mint);
+        assert_eq!(stake_receipt.start_staking, 1000);
+        assert_eq!(stake_account.nfts_staked.len(), 1);
+        assert_eq!(stake_account.nfts_staked[0].multiplier_bps, 12_500);
+
+        assert!(matches!(
+            release_stake(&mut rewarder, &mut stake_account, &mut vault_account, &stake_receipt, 1007),
+            Err(StakingError::NFTAUnlocked)
+        ));
+
+        // rewards up to the unstake are settled before the nft is removed
+        release_stake(&mut rewarder, &mut stake_account, &mut vault_account, &stake_receipt, 1000 + 86_400).unwrap();
+        assert_eq!(vault_account.total_staked, 0);
+        assert!(stake_account.nfts_staked.is_empty());
+        assert_eq!(stake_account.claimed_reward, 125);
+    }
+
+    #[test]
+    pub fn test_token_2022_mint_extensions() {
+        use anchor_lang::solana_program::program_option::COption;
+        use anchor_lang::solana_program::program_pack::Pack;
+        use token_interface::extension::*;
+
+        // a token-2022 mint with a transfer fee config
+        let mut data = vec![0u8; 166 + 4 + 108];
+        spl_token::state::Mint {
+            mint_authority: COption::None,
+            supply: 1,
+            decimals: 0,
+            is_initialized: true,
+            freeze_authority: COption::None,
+        }
+        .pack_into_slice(&mut data[..spl_token::state::Mint::LEN]);
+        data[165] = 1;
+        data[166..168].copy_from_slice(&TRANSFER_FEE_CONFIG.to_le_bytes());
+        data[168..170].copy_from_slice(&108u16.to_le_bytes());
+
+        let key = Pubkey::new_unique();
+        let owner = token_interface::spl_token_2022::ID;
+        let mut lamports = 0;
+        let info = AccountInfo::new(&key, false, false, &mut lamports, &mut data, &owner, false, 0);
+
+        assert!(matches!(
+            token_interface::load_mint(&info, &spl_token::ID),
+            Err(StakingError::InvalidTokenProgram)
+        ));
+        let mint = token_interface::load_mint(&info, &owner).unwrap();
+        assert_eq!(mint.supply, 1);
+        assert_eq!(mint.extensions, vec![TRANSFER_FEE_CONFIG]);
+        // token accounts of the mint carry the withheld transfer fee amount
+        assert_eq!(mint.token_account_len(), 166 + 4 + 8);
+
+        assert!(matches!(
+            check_nft_mint_extensions(&mint.extensions),
+            Err(StakingError::UnsupportedMintExtension)
+        ));
+        assert!(check_reward_mint_extensions(&mint.extensions, RewardSource::Treasury).is_ok());
+
+        assert!(check_reward_mint_extensions(&[NON_TRANSFERABLE], RewardSource::Mint).is_ok());
+        assert!(matches!(
+            check_reward_mint_extensions(&[NON_TRANSFERABLE], RewardSource::Treasury),
+            Err(StakingError::UnsupportedMintExtension)
+        ));
+        assert!(matches!(
+            check_reward_mint_extensions(&[TRANSFER_HOOK], RewardSource::Mint),
+            Err(StakingError::UnsupportedMintExtension)
+        ));
+        assert!(check_nft_mint_extensions(&[]).is_ok());
+    }
+
+    #[test]
+    pub fn test_verified_collection() {
+        use metaplex_token_metadata::state::{Data, Key, Metadata};
+
+        let nft_mint = Pubkey::new_unique();
+        let collection_mint = Pubkey::new_unique();
+        let mut rewarder = NftStakeRewarder {
+            collection: "gmoot".to_string(),
+            ..Default::default()
+        };
+
+        let metadata_data = |name: &str, collection: Option<(bool, Pubkey)>| {
+            let mut data = Metadata {
+                key: Key::MetadataV1,
+                update_authority: Pubkey::default(),
+                mint: nft_mint,
+                data: Data {
+                    name: name.to_string(),
+                    symbol: String::new(),
+                    uri: String::new(),
+                    seller_fee_basis_points: 0,
+                    creators: Some(vec![]),
+                },
+                primary_sale_happened: false,
+                is_mutable: true,
+                edition_nonce: None,
+            }
+            .try_to_vec()
+            .unwrap();
+            // no token standard
+            data.push(0);
+            match collection {
+                Some((verified, key)) => {
+                    data.extend([1, verified as u8]);
+                    data.extend(key.to_bytes());
+                }
+                None => data.push(0),
+            }
+            data.resize(MetadataAccount::LEN, 0);
+            data
+        };
+        let (metadata_key, _) = Pubkey::find_program_address(
+            &[
+                anchor_metaplex::PDAPrefix.as_bytes(),
+                &anchor_metaplex::ID.to_bytes(),
+                &nft_mint.to_bytes(),
+            ],
+            &anchor_metaplex::ID,
+        );
+        let check = |data: &mut Vec<u8>, rewarder: &NftStakeRewarder| {
+            let mut lamports = 0;
+            let info = AccountInfo::new(
+                &metadata_key,
+                false,
+                false,
+                &mut lamports,
+                data,
+                &anchor_metaplex::ID,
+                false,
+                0,
+            );
+            let metadata = load_metadata_account(&info).unwrap();
+            check_metadata(&metadata, &nft_mint, rewarder)
+        };
+
+        // without a collection mint only the name prefix is checked
+        assert!(check(&mut metadata_data("gmoot bag #69", None), &rewarder).is_ok());
+        assert!(matches!(
+            check(&mut metadata_data("fake bag #69", Some((true, collection_mint))), &rewarder),
+            Err(StakingError::InvalidMetadataCollectionPrefix)
+        ));
+
+        rewarder.collection_mint = Some(collection_mint);
+        assert!(check(&mut metadata_data("fake bag #69", Some((true, collection_mint))), &rewarder).is_ok());
+        assert!(matches!(
+            check(&mut metadata_data("gmoot bag #69", Some((false, collection_mint))), &rewarder),
+            Err(StakingError::UnverifiedCollectionMembership)
+        ));
+        assert!(matches!(
+            check(&mut metadata_data("gmoot bag #69", Some((true, Pubkey::new_unique()))), &rewarder),
+            Err(StakingError::UnverifiedCollectionMembership)
+        ));
+        assert!(matches!(
+            check(&mut metadata_data("gmoot bag #69", None), &rewarder),
+            Err(StakingError::UnverifiedCollectionMembership)
+        ));
+    }
+
+    #[test]
+    pub fn test_whitelist() {
+        let mints: Vec<Pubkey> = (0..5).map(|_| Pubkey::new_unique()).collect();
+        let mut rewarder = test_rewarder(100);
+        rewarder.add_whitelist_addresses(&[mints[0], mints[1], mints[0]], 0);
+        assert_eq!(rewarder.whitelist_addresses, vec![mints[0], mints[1]]);
+        assert_eq!(rewarder.total_whitelist_address, 2);
+        // only the removals of staked mints are recorded
+        rewarder.remove_whitelist_addresses(&[mints[0], mints[1], mints[2]], &[mints[1], mints[2]], 0).unwrap();
+        assert_eq!(rewarder.total_whitelist_address, 0);
+        assert_eq!(rewarder.whitelist_removals.len(), 1);
+        assert!(rewarder.is_removal_recorded(&mints[1]));
+        // and they are dropped once the mint is unstaked
+        rewarder.prune_whitelist_removals(&mints[1], None);
+        assert!(rewarder.whitelist_removals.is_empty());
+
+        // the removals the rewarder records are bounded
+        let staked_mints: Vec<Pubkey> = (0..=MAX_WHITELIST_REMOVALS).map(|_| Pubkey::new_unique()).collect();
+        rewarder.add_whitelist_addresses(&staked_mints, 0);
+        assert!(matches!(
+            rewarder.remove_whitelist_addresses(&staked_mints, &staked_mints, 0),
+            Err(StakingError::TooManyWhitelistRemovals)
+        ));
+        rewarder.remove_whitelist_addresses(&staked_mints[1..], &staked_mints, 0).unwrap();
+        assert_eq!(rewarder.whitelist_removals.len(), MAX_WHITELIST_REMOVALS);
+
+        rewarder.add_whitelist_addresses(&[mints[0]], 0);
+        assert!(rewarder.is_whitelisted(&mints[0], &[]));
+        assert!(!rewarder.is_whitelisted(&mints[1], &[]));
+
+        // a tree over the first four mints
+        let leaves: Vec<[u8; 32]> = mints[..4]
+            .iter()
+            .map(|mint| merkle::leaf_hash(&[mint.as_ref()]))
+            .collect();
+        let left = merkle::node_hash(&leaves[0], &leaves[1]);
+        let right = merkle::node_hash(&leaves[2], &leaves[3]);
+        rewarder.whitelist_root = Some(merkle::node_hash(&left, &right));
+
+        assert!(rewarder.is_whitelisted(&mints[0], &[leaves[1], right]));
+        assert!(rewarder.is_whitelisted(&mints[3], &[leaves[2], left]));
+        // the root replaces the list of addresses
+        assert!(!rewarder.is_whitelisted(&mints[0], &[]));
+        assert!(!rewarder.is_whitelisted(&mints[1], &[leaves[1], right]));
+        assert!(!rewarder.is_whitelisted(&mints[4], &[leaves[3], left]));
+        // an inner node can not be passed off as a mint
+        assert!(!rewarder.is_whitelisted(&Pubkey::new_from_array(left), &[right]));
+    }
+
+    #[test]
+    pub fn test_rarity_weights() {
+        let legendary = Pubkey::new_unique();
+        let common = Pubkey::new_unique();
+        let mut rewarder = test_rewarder(2400);
+
+        // every nft weighs 1x without a rarity root
+        assert_eq!(rewarder.rarity_weight_bps(&legendary, None).unwrap(), BPS_DENOMINATOR);
+
+        let legendary_leaf = merkle::leaf_hash(&[legendary.as_ref(), &30_000u32.to_le_bytes()]);
+        let common_leaf = merkle::leaf_hash(&[common.as_ref(), &5_000u32.to_le_bytes()]);
+        rewarder.rarity_root = Some(merkle::node_hash(&legendary_leaf, &common_leaf));
+
+        let legendary_weight = RarityWeight { weight_bps: 30_000, proof: vec![common_leaf] };
+        assert_eq!(rewarder.rarity_weight_bps(&legendary, Some(&legendary_weight)).unwrap(), 30_000);
+        let common_weight = RarityWeight { weight_bps: 5_000, proof: vec![legendary_leaf] };
+        assert_eq!(rewarder.rarity_weight_bps(&common, Some(&common_weight)).unwrap(), 5_000);
+
+        // the weight has to be proven once the root is set
+        assert!(matches!(
+            rewarder.rarity_weight_bps(&common, None),
+            Err(StakingError::InvalidRarityProof)
+        ));
+        let inflated_weight = RarityWeight { weight_bps: 30_000, proof: vec![legendary_leaf] };
+        assert!(matches!(
+            rewarder.rarity_weight_bps(&common, Some(&inflated_weight)),
+            Err(StakingError::InvalidRarityProof)
+        ));
+
+        // the weight scales the reward together with the lock multiplier
+        let multiplier_bps = rewarder.lock_multiplier_bps(7);
+        assert_eq!(calculate_reward(&rewarder, multiplier_bps, 30_000, 1, 3600).unwrap(), 375);
+        assert_eq!(calculate_reward(&rewarder, multiplier_bps, 5_000, 1, 3600).unwrap(), 62);
+    }
+
+    #[test]
+    pub fn test_trait_boosts() {
+        use metaplex_token_metadata::state::Data;
+
+        let nft_mint = Pubkey::new_unique();
+        let mut rewarder = test_rewarder(2400);
+        rewarder.trait_boosts = vec![
+            TraitBoost {
+                rule: TraitRule::Symbol { symbol: "GMOOT".to_string() },
+                multiplier_bps: 12_000,
+            },
+            TraitBoost {
+                rule: TraitRule::NameSuffix { suffix: "(Gold)".to_string() },
+                multiplier_bps: 20_000,
+            },
+            TraitBoost {
+                rule: TraitRule::SellerFeeBasisPoints { seller_fee_basis_points: 750 },
+                multiplier_bps: 15_000,
+            },
+        ];
+        assert!(check_trait_boosts(&rewarder.trait_boosts).is_ok());
+
+        // strings are padded with null characters in the metadata
+        let data = |name: &str, symbol: &str, seller_fee_basis_points: u16| Data {
+            name: format!("{:\0<32}", name),
+            symbol: format!("{:\0<10}", symbol),
+            uri: String::new(),
+            seller_fee_basis_points,
+            creators: None,
+        };
+
+        assert_eq!(rewarder.trait_multiplier_bps(&data("gmoot bag #1", "BAG", 500)), BPS_DENOMINATOR);
+        assert_eq!(rewarder.trait_multiplier_bps(&data("gmoot bag #1", "GMOOT", 500)), 12_000);
+        // the highest matching boost applies
+        assert_eq!(rewarder.trait_multiplier_bps(&data("gmoot bag #1 (Gold)", "GMOOT", 750)), 20_000);
+        assert_eq!(rewarder.trait_multiplier_bps(&data("gmoot bag #1", "GMOOTS", 750)), 15_000);
+
+        // the trait boost is recorded together with the rarity weight, only when metadata is enforced
+        let gold = data("gmoot bag #1 (Gold)", "BAG", 500);
+        assert_eq!(rewarder.weight_bps(&nft_mint, None, Some(&gold)).unwrap(), 20_000);
+        assert_eq!(rewarder.weight_bps(&nft_mint, None, None).unwrap(), BPS_DENOMINATOR);
+        let leaf = merkle::leaf_hash(&[nft_mint.as_ref(), &5_000u32.to_le_bytes()]);
+        rewarder.rarity_root = Some(leaf);
+        let rarity = RarityWeight { weight_bps: 5_000, proof: vec![] };
+        assert_eq!(rewarder.weight_bps(&nft_mint, Some(&rarity), Some(&gold)).unwrap(), 10_000);
+
+        let mut trait_boosts = rewarder.trait_boosts.clone();
+        trait_boosts.push(trait_boosts[0].clone());
+        assert!(matches!(check_trait_boosts(&trait_boosts), Err(StakingError::DuplicateBoostTier)));
+        trait_boosts[3].rule = TraitRule::NameSuffix { suffix: String::new() };
+        assert!(matches!(check_trait_boosts(&trait_boosts), Err(StakingError::InvalidTraitBoost)));
+        trait_boosts[3].rule = TraitRule::NameSuffix { suffix: "x".repeat(MAX_TRAIT_PATTERN_LEN + 1) };
+        assert!(matches!(check_trait_boosts(&trait_boosts), Err(StakingError::InvalidTraitBoost)));
+        let trait_boosts = vec![trait_boosts[0].clone(); MAX_TRAIT_BOOSTS + 1];
+        assert!(matches!(check_trait_boosts(&trait_boosts), Err(StakingError::TooManyBoostTiers)));
+    }
+
+    #[test]
+    pub fn test_pause() {
+        let nft_mint = Pubkey::new_unique();
+        let mut rewarder = test_rewarder(2400);
+        rewarder.whitelist_addresses = vec![nft_mint];
+        let mut stake_account = test_stake_account(vec![]);
+        let mut vault_account = test_vault_account();
+        let mut stake_receipt = test_stake_receipt();
+
+        let stake_paused = PauseFlags { stake: true, ..Default::default() };
+        rewarder.set_paused(stake_paused, 0);
+        assert!(matches!(
+            record_stake(&mut rewarder, &mut stake_account, &mut vault_account, &mut stake_receipt, nft_mint, 7, 0),
+            Err(StakingError::StakingPaused)
+        ));
+        rewarder.set_paused(PauseFlags::default(), 0);
+        record_stake(&mut rewarder, &mut stake_account, &mut vault_account, &mut stake_receipt, nft_mint, 7, 0).unwrap();
+
+        // nothing accrues while accrual is paused, even when the stake account is not settled in between
+        let accrual_paused = PauseFlags { accrual: true, ..Default::default() };
+        rewarder.set_paused(accrual_paused, 3600);
+        settle_rewards(&mut rewarder, &mut stake_account, 5400).unwrap();
+        assert_eq!(stake_account.claimed_reward, 125);
+        rewarder.set_paused(PauseFlags::default(), 7200);
+        rewarder.set_paused(accrual_paused, 10_800);
+        rewarder.set_paused(PauseFlags::default(), 14_400);
+        assert_eq!(rewarder.accrual_clock_at(14_400), 14_400 - 7200);
+        settle_rewards(&mut rewarder, &mut stake_account, 18_000).unwrap();
+        assert_eq!(stake_account.claimed_reward, 125 + 250);
+
+        // an nft staked during a pause only accrues once it is lifted
+        rewarder.set_paused(accrual_paused, 18_000);
+        stake_receipt.nft_mint = Pubkey::default();
+        rewarder.whitelist_addresses.push(Pubkey::default());
+        record_stake(&mut rewarder, &mut stake_account, &mut vault_account, &mut stake_receipt, Pubkey::default(), 7, 19_800).unwrap();
+        rewarder.set_paused(PauseFlags::default(), 21_600);
+        settle_rewards(&mut rewarder, &mut stake_account, 25_200).unwrap();
+        assert_eq!(stake_account.nfts_staked[1].accrued, 125);
+
+        assert!(PauseFlags::default().is_subset_of(&stake_paused));
+        assert!(!accrual_paused.is_subset_of(&stake_paused));
+    }
+
+    #[test]
+    pub fn test_forfeit_stake() {
+        let locked_mint = Pubkey::new_unique();
+        let other_mint = Pubkey::new_unique();
+        let mut rewarder = test_rewarder(2400);
+        rewarder.whitelist_addresses = vec![locked_mint, other_mint];
+        let mut stake_account = test_stake_account(vec![]);
+        let mut vault_account = test_vault_account();
+        let mut stake_receipt = test_stake_receipt();
+        let mut other_receipt = stake_receipt.clone();
+
+        record_stake(&mut rewarder, &mut stake_account, &mut vault_account, &mut other_receipt, other_mint, 7, 0).unwrap();
+        record_stake(&mut rewarder, &mut stake_account, &mut vault_account, &mut stake_receipt, locked_mint, 30, 3600).unwrap();
+        assert_eq!(stake_account.claimed_reward, 125);
+        assert_eq!(rewarder.total_outstanding_rewards, 125);
+
+        // claiming first leaves nothing to forfeit, so the lock still applies to the emergency unstake
+        assert!(matches!(
+            forfeit_stake(&mut rewarder, &mut stake_account, &mut vault_account, &stake_receipt, 3610),
+            Err(StakingError::NFTAUnlocked)
+        ));
+        assert_eq!(stake_account.nfts_staked.len(), 2);
+
+        // once the lock ends the nft comes out without settling, and every unclaimed reward is forfeited
+        forfeit_stake(&mut rewarder, &mut stake_account, &mut vault_account, &stake_receipt, 7200).unwrap();
+        assert_eq!(vault_account.total_staked, 1);
+        assert_eq!(stake_account.nfts_staked.len(), 1);
+        assert_eq!(stake_account.nfts_staked[0].nft_mint, other_mint);
+        assert_eq!(stake_account.claimed_reward, 0);
+        assert_eq!(rewarder.total_outstanding_rewards, 0);
+
+        // the remaining nft accrues again from the emergency unstake on
+        settle_rewards(&mut rewarder, &mut stake_account, 10_800).unwrap();
+        assert_eq!(stake_account.claimed_reward, 125);
+    }
+
+    #[test]
+    pub fn test_early_unstake_policy() {
+        let nft_mint = Pubkey::new_unique();
+        let mut stake_receipt = test_stake_receipt();
+        let mut vault_account = test_vault_account();
+
+        // an nft locked for two hours at 1.5x accrues 150 in its first hour, 50 of them from its lock tier,
+        // next to an unlocked nft accruing 125
+        let other_mint = Pubkey::new_unique();
+        let mut unstake_early = |early_unstake_policy: EarlyUnstakePolicy, current_time: i64, claim_first: bool| {
+            let mut rewarder = test_rewarder(2400);
+            rewarder.lock_tiers.push(LockTier { locking_period: 7200, multiplier_bps: 15_000 });
+            rewarder.whitelist_addresses = vec![nft_mint, other_mint];
+            rewarder.early_unstake_policy = early_unstake_policy;
+            let mut stake_account = test_stake_account(vec![]);
+            let mut other_receipt = stake_receipt.clone();
+            record_stake(&mut rewarder, &mut stake_account, &mut vault_account, &mut other_receipt, other_mint, 7, 0).unwrap();
+            record_stake(&mut rewarder, &mut stake_account, &mut vault_account, &mut stake_receipt, nft_mint, 7200, 0).unwrap();
+            if claim_first {
+                settle_rewards(&mut rewarder, &mut stake_account, current_time).unwrap();
+                rewarder.total_outstanding_rewards -= stake_account.claimed_reward;
+                stake_account.claimed_reward = 0;
+                for nft_staked in stake_account.nfts_staked.iter_mut() {
+                    nft_staked.accrued = 0;
+                }
+            }
+            release_stake(&mut rewarder, &mut stake_account, &mut vault_account, &stake_receipt, current_time)
+                .map(|fee| (fee, stake_account.claimed_reward, rewarder.total_outstanding_rewards))
+        };
+
+        assert!(matches!(
+            unstake_early(EarlyUnstakePolicy::HardLock, 3600, false),
+            Err(StakingError::NFTAUnlocked)
+        ));
+        assert_eq!(unstake_early(EarlyUnstakePolicy::ForfeitLockBoost, 3600, false).unwrap(), (0, 225, 225));
+        // only the rewards of the nft leaving early are slashed
+        assert_eq!(unstake_early(EarlyUnstakePolicy::Slash { slash_bps: 2_000 }, 3600, false).unwrap(), (0, 245, 245));
+        // the fee is paid on top of the rewards, even without any pending
+        assert_eq!(unstake_early(EarlyUnstakePolicy::Fee { amount: 150 }, 3600, false).unwrap(), (150, 275, 275));
+        assert_eq!(unstake_early(EarlyUnstakePolicy::Fee { amount: 150 }, 0, false).unwrap(), (150, 0, 0));
+        assert_eq!(unstake_early(EarlyUnstakePolicy::Fee { amount: 150 }, 7201, false).unwrap(), (0, 550, 550));
+
+        // claiming right before leaving early does not dodge the penalty, the claimed part is paid back as a fee
+        assert_eq!(unstake_early(EarlyUnstakePolicy::ForfeitLockBoost, 3600, true).unwrap(), (50, 0, 0));
+        assert_eq!(unstake_early(EarlyUnstakePolicy::Slash { slash_bps: 2_000 }, 3600, true).unwrap(), (30, 0, 0));
+        assert_eq!(unstake_early(EarlyUnstakePolicy::Fee { amount: 150 }, 3600, true).unwrap(), (150, 0, 0));
+    }
+
+    #[test]
+    pub fn test_unbonding() {
+        let nft_mint = Pubkey::new_unique();
+        let other_mint = Pubkey::new_unique();
+        let mut rewarder = test_rewarder(2400);
+        rewarder.whitelist_addresses = vec![nft_mint, other_mint];
+        rewarder.unbonding_period = 3600;
+        let mut stake_account = test_stake_account(vec![]);
+        let mut vault_account = test_vault_account();
+        let mut stake_receipt = test_stake_receipt();
+        let mut other_receipt = stake_receipt.clone();
+
+        record_stake(&mut rewarder, &mut stake_account, &mut vault_account, &mut stake_receipt, nft_mint, 7, 0).unwrap();
+        record_stake(&mut rewarder, &mut stake_account, &mut vault_account, &mut other_receipt, other_mint, 7, 0).unwrap();
+        assert!(matches!(
+            release_stake(&mut rewarder, &mut stake_account, &mut vault_account, &stake_receipt, 3600),
+            Err(StakingError::UnbondingRequired)
+        ));
+        assert!(matches!(
+            forfeit_stake(&mut rewarder, &mut stake_account, &mut vault_account, &stake_receipt, 3600),
+            Err(StakingError::UnbondingRequired)
+        ));
+
+        // the nft stops accruing once unstaking is requested
+        request_release(&mut rewarder, &mut stake_account, &stake_receipt, 3600).unwrap();
+        assert_eq!(stake_account.claimed_reward, 250);
+        assert_eq!(stake_account.nfts_staked[0].unbonding_until, Some(7200));
+        assert!(matches!(
+            request_release(&mut rewarder, &mut stake_account, &stake_receipt, 3600),
+            Err(StakingError::UnstakeAlreadyRequested)
+        ));
+        assert!(matches!(
+            release_stake(&mut rewarder, &mut stake_account, &mut vault_account, &stake_receipt, 7199),
+            Err(StakingError::UnbondingNotElapsed)
+        ));
+        assert!(matches!(
+            forfeit_stake(&mut rewarder, &mut stake_account, &mut vault_account, &stake_receipt, 7199),
+            Err(StakingError::UnbondingNotElapsed)
+        ));
+        release_stake(&mut rewarder, &mut stake_account, &mut vault_account, &stake_receipt, 7200).unwrap();
+        assert_eq!(vault_account.total_staked, 1);
+        assert_eq!(stake_account.nfts_staked.len(), 1);
+        assert_eq!(stake_account.claimed_reward, 250 + 125);
+    }
+
+    #[test]
+    pub fn test_extend_lock() {
+        let nft_mint = Pubkey::new_unique();
+        let mut rewarder = test_rewarder(2400);
+        rewarder.whitelist_addresses = vec![nft_mint];
+        let mut stake_account = test_stake_account(vec![]);
+        let mut vault_account = test_vault_account();
+        let mut stake_receipt = test_stake_receipt();
+
+        record_stake(&mut rewarder, &mut stake_account, &mut vault_account, &mut stake_receipt, nft_mint, 7, 0).unwrap();
+        assert!(matches!(
+            extend_stake(&mut rewarder, &mut stake_account, &mut stake_receipt, 8, 3600),
+            Err(StakingError::InvalidLockingPeriod)
+        ));
+
+        // the first hour accrues at 1.25x and the second at 1.5x
+        extend_stake(&mut rewarder, &mut stake_account, &mut stake_receipt, 30, 3600).unwrap();
+        assert_eq!(stake_account.claimed_reward, 125);
+        assert_eq!(stake_receipt.start_staking, 3600);
+        assert_eq!(stake_receipt.locking_period, 30);
+        assert!(stake_receipt.is_locked(3630));
+        // the first lock had ended, so early unstake penalties only count what accrues from now on
+        assert_eq!(stake_account.nfts_staked[0].accrued_in_lock, 0);
+        settle_rewards(&mut rewarder, &mut stake_account, 7200).unwrap();
+        assert_eq!(stake_account.claimed_reward, 125 + 150);
+        assert_eq!(stake_account.nfts_staked[0].accrued_in_lock, 150);
+        assert_eq!(stake_account.nfts_staked[0].lock_boost_accrued_in_lock, 50);
+
+        assert!(matches!(
+            extend_stake(&mut rewarder, &mut stake_account, &mut stake_receipt, 7, 7200),
+            Err(StakingError::LockCannotBeShortened)
+        ));
+        assert_eq!(stake_account.nfts_staked[0].multiplier_bps, 15_000);
+    }
+
+    #[test]
+    pub fn test_unsettled_rewards() {
+        let nft_mint = Pubkey::new_unique();
+        let mut rewarder = test_rewarder(2400);
+        rewarder.quantity_tiers = vec![];
+        rewarder.whitelist_addresses = vec![nft_mint];
+        let mut stake_account = test_stake_account(vec![]);
+        let mut vault_account = test_vault_account();
+        let mut stake_receipt = test_stake_receipt();
+
+        // rewards count as accrued before anything settles them into the stake account
+        record_stake(&mut rewarder, &mut stake_account, &mut vault_account, &mut stake_receipt, nft_mint, 7, 0).unwrap();
+        assert_eq!(rewarder.unsettled_rewards(3600), 125);
+        settle_rewards(&mut rewarder, &mut stake_account, 3600).unwrap();
+        assert_eq!(rewarder.total_outstanding_rewards, 125);
+        assert_eq!(rewarder.unsettled_rewards(3600), 0);
+
+        // nothing accrues while paused or past the end of the emission, rounding up
+        rewarder.set_paused(PauseFlags { accrual: true, ..Default::default() }, 3600);
+        assert_eq!(rewarder.unsettled_rewards(7200), 0);
+        rewarder.set_paused(PauseFlags::default(), 7200);
+        rewarder.advance_accrual_clock(9000);
+        rewarder.emission_end_ts = Some(9000);
+        assert_eq!(rewarder.unsettled_rewards(14_400), 63);
+        settle_rewards(&mut rewarder, &mut stake_account, 14_400).unwrap();
+        assert_eq!(stake_account.claimed_reward, 125 + 62);
+        assert_eq!(rewarder.unsettled_rewards(14_400), 0);
+
+        // the reward rate applies to everything that is not settled yet, like it does when settling
+        rewarder.advance_accrual_clock(14_400);
+        rewarder.emission_end_ts = None;
+        rewarder.reward_rate = 4800;
+        assert_eq!(rewarder.unsettled_rewards(18_000), 250);
+
+        // the highest quantity tier bounds the boosts of every stake account
+        rewarder.quantity_tiers = test_rewarder(0).quantity_tiers;
+        assert_eq!(rewarder.unsettled_rewards(18_000), 500);
+
+        release_stake(&mut rewarder, &mut stake_account, &mut vault_account, &stake_receipt, 18_000).unwrap();
+        assert_eq!(rewarder.total_reward_weight, 0);
+        assert_eq!(rewarder.unsettled_rewards(21_600), 0);
+    }
+
+    #[test]
+    pub fn test_cnft_metadata() {
+        use anchor_bubblegum::{Collection, TokenProgramVersion};
+
+        let collection_mint = Pubkey::new_unique();
+        let mut rewarder = NftStakeRewarder {
+            collection: "gmoot".to_string(),
+            ..Default::default()
+        };
+        let mut metadata = MetadataArgs {
+            name: "gmoot #1".to_string(),
+            symbol: String::new(),
+            uri: String::new(),
+            seller_fee_basis_points: 500,
+            primary_sale_happened: false,
+            is_mutable: true,
+            edition_nonce: None,
+            token_standard: None,
+            collection: None,
+            uses: None,
+            token_program_version: TokenProgramVersion::Original,
+            creators: vec![],
+        };
+        let creator_hash = rewarder.creator_hash();
+        let leaf_of = |metadata: &MetadataArgs| LeafArgs {
+            root: [0; 32],
+            data_hash: anchor_bubblegum::hash_metadata(metadata).unwrap(),
+            creator_hash,
+            nonce: 0,
+            index: 0,
+        };
+
+        let leaf = leaf_of(&metadata);
+        assert!(check_cnft_metadata(&metadata, &leaf, &rewarder).is_ok());
+
+        // the metadata has to be the one hashed into the leaf
+        let mut other_metadata = metadata.clone();
+        other_metadata.seller_fee_basis_points = 0;
+        assert!(matches!(
+            check_cnft_metadata(&other_metadata, &leaf, &rewarder),
+            Err(StakingError::InvalidCnftMetadata)
+        ));
+
+        metadata.name = "other #1".to_string();
+        let leaf = leaf_of(&metadata);
+        assert!(matches!(
+            check_cnft_metadata(&metadata, &leaf, &rewarder),
+            Err(StakingError::InvalidMetadataCollectionPrefix)
+        ));
+
+        // the verified collection replaces the name check
+        rewarder.collection_mint = Some(collection_mint);
+        assert!(matches!(
+            check_cnft_metadata(&metadata, &leaf, &rewarder),
+            Err(StakingError::UnverifiedCollectionMembership)
+        ));
+        metadata.collection = Some(Collection { verified: false, key: collection_mint });
+        let leaf = leaf_of(&metadata);
+        assert!(matches!(
+            check_cnft_metadata(&metadata, &leaf, &rewarder),
+            Err(StakingError::UnverifiedCollectionMembership)
+        ));
+        metadata.collection = Some(Collection { verified: true, key: collection_mint });
+        let leaf = leaf_of(&metadata);
+        assert!(check_cnft_metadata(&metadata, &leaf, &rewarder).is_ok());
+
+        let mut leaf = leaf;
+        leaf.creator_hash = [1; 32];
+        assert!(matches!(
+            check_cnft_metadata(&metadata, &leaf, &rewarder),
+            Err(StakingError::InvalidMetadataCreators)
+        ));
+    }
+
+    #[test]
+    pub fn test_migrate_legacy_accounts() {
+        let mut rewarder = test_rewarder(100);
+        rewarder.total_outstanding_rewards = 50;
+        let legacy_vault_account = LegacyVaultAccount {
+            total_staked: 0,
+            reward_mint: Pubkey::new_unique(),
+            nft_items_staked: vec![LegacyNftItem { flag: false, ..Default::default() }],
+            bump: 254,
+        };
+        let legacy_stake_account = LegacyNftStakeAccount {
+            owner: Pubkey::new_unique(),
+            rewarder: Pubkey::new_unique(),
+            nfts_staked: vec![LegacyNftStaked { locking_period: 7, num_staked: 0 }],
+            bump: 253,
+            last_claimed: 3600,
+            claimed_reward: 25,
+        };
+
+        // accounts still holding nfts cannot be migrated, those nfts have no receipt
+        let mut staked_vault_account = legacy_vault_account.clone();
+        staked_vault_account.total_staked = 1;
+        assert!(matches!(
+            migrate_legacy_accounts(&mut rewarder, staked_vault_account, legacy_stake_account.clone()),
+            Err(StakingError::LegacyStakeNotEmpty)
+        ));
+        let mut staked_stake_account = legacy_stake_account.clone();
+        staked_stake_account.nfts_staked[0].num_staked = 1;
+        assert!(matches!(
+            migrate_legacy_accounts(&mut rewarder, legacy_vault_account.clone(), staked_stake_account),
+            Err(StakingError::LegacyStakeNotEmpty)
+        ));
+        assert_eq!(rewarder.total_outstanding_rewards, 50);
+
+        let (vault_account, stake_account) =
+            migrate_legacy_accounts(&mut rewarder, legacy_vault_account.clone(), legacy_stake_account.clone()).unwrap();
+        assert_eq!(vault_account.total_staked, 0);
+        assert_eq!(vault_account.reward_mint, legacy_vault_account.reward_mint);
+        assert_eq!(vault_account.bump, 254);
+        assert_eq!(stake_account.owner, legacy_stake_account.owner);
+        assert_eq!(stake_account.rewarder, legacy_stake_account.rewarder);
+        assert!(stake_account.nfts_staked.is_empty());
+        assert_eq!(stake_account.bump, 253);
+        // the unclaimed rewards of the legacy stake account are kept, and owed by the rewarder
+        assert_eq!(stake_account.claimed_reward, 25);
+        assert_eq!(rewarder.total_outstanding_rewards, 50 + 25);
+    }
+
+    #[test]
+    pub fn test_allowed_locking_periods() {
+        let rewarder = test_rewarder(0);
+        assert!(rewarder.is_allowed_locking_period(7));
+        assert!(rewarder.is_allowed_locking_period(30));
+        assert!(!rewarder.is_allowed_locking_period(0));
+        assert!(!rewarder.is_allowed_locking_period(-7));
+    }
+
+    #[test]
+    pub fn test_boost_tier_validation() {
+        let rewarder = test_rewarder(0);
+        assert!(check_boost_tiers(&rewarder.lock_tiers, &rewarder.quantity_tiers).is_ok());
+
+        let duplicate_lock = vec![rewarder.lock_tiers[0].clone(), rewarder.lock_tiers[0].clone()];
+        assert!(matches!(
+            check_boost_tiers(&duplicate_lock, &[]),
+            Err(StakingError::DuplicateBoostTier)
+        ));
+
+        let negative_lock = vec![LockTier { locking_period: -1, multiplier_bps: 10_000 }];
+        assert!(matches!(
+            check_boost_tiers(&negative_lock, &[]),
+            Err(StakingError::InvalidLockingPeriod)
+        ));
+
+        let over_max_lock = vec![LockTier { locking_period: MAX_LOCKING_PERIOD + 1, multiplier_bps: 10_000 }];
+        assert!(matches!(
+            check_boost_tiers(&over_max_lock, &[]),
+            Err(StakingError::InvalidLockingPeriod)
+        ));
+
+        let too_many = vec![QuantityTier::default(); MAX_QUANTITY_TIERS + 1];
+        assert!(matches!(
+            check_boost_tiers(&[], &too_many),
+            Err(StakingError::TooManyBoostTiers)
+        ));
     }
 }
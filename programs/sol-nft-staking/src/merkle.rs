@@ -0,0 +1,26 @@
+use anchor_lang::solana_program::keccak;
+
+// leaves and inner nodes are hashed with different prefixes so a node can not be passed off as a leaf
+const LEAF_PREFIX: &[u8] = &[0];
+const NODE_PREFIX: &[u8] = &[1];
+
+/// The hash of a leaf made of the concatenation of `data`
+pub fn leaf_hash(data: &[&[u8]]) -> [u8; 32] {
+    let mut values = vec![LEAF_PREFIX];
+    values.extend_from_slice(data);
+    keccak::hashv(&values).0
+}
+
+/// The hash of two sibling nodes, taken in sorted order so proofs do not need to encode the side
+pub fn node_hash(a: &[u8; 32], b: &[u8; 32]) -> [u8; 32] {
+    let (left, right) = if a <= b { (a, b) } else { (b, a) };
+    keccak::hashv(&[NODE_PREFIX, left, right]).0
+}
+
+/// whether `proof` leads from `leaf` up to `root`
+pub fn verify(proof: &[[u8; 32]], root: &[u8; 32], leaf: [u8; 32]) -> bool {
+    let computed_root = proof
+        .iter()
+        .fold(leaf, |node, sibling| node_hash(&node, sibling));
+    computed_root == *root
+}
@@ -1,7 +1,11 @@
 use std::mem::size_of;
 
 use anchor_lang::prelude::*;
-use metaplex_token_metadata::state::Creator;
+use anchor_lang::solana_program::keccak;
+use metaplex_token_metadata::state::{Creator, Data};
+
+use crate::errors::StakingError;
+use crate::merkle;
 
 pub trait Len {
     const LEN: usize;
@@ -14,9 +18,22 @@ where
     const LEN: usize = 8 + size_of::<T>();
 }
 
-
+/// multipliers are expressed in basis points where 10_000 is 1x
+pub const BPS_DENOMINATOR: u64 = 10_000;
+pub const MAX_LOCK_TIERS: usize = 8;
+pub const MAX_QUANTITY_TIERS: usize = 8;
+pub const MAX_TRAIT_BOOSTS: usize = 8;
+/// as long as the name in the metadata, the longest field a trait rule can match
+pub const MAX_TRAIT_PATTERN_LEN: usize = 32;
+/// the longest locking period a lock tier may offer, one year in seconds
+pub const MAX_LOCKING_PERIOD: i64 = 365 * 24 * 3600;
+/// bounded by the space of the stake account
+pub const MAX_NFTS_PER_STAKE_ACCOUNT: usize = 64;
+/// bounded by the space of the rewarder, only staked mints are recorded
+pub const MAX_WHITELIST_REMOVALS: usize = 32;
 
 #[account]
+#[derive(Default)]
 pub struct NftStakeRewarder {
     pub authority: Pubkey,
     pub reward_mint: Pubkey,
@@ -37,8 +54,408 @@ pub struct NftStakeRewarder {
     pub whitelist_addresses: Vec<Pubkey>,
     /// the total number of whitelist addresses
     pub total_whitelist_address: u64,
+    /// reward boosts applied to NFTs staked with a matching locking period,
+    /// these are also the only locking periods accepted when staking
+    pub lock_tiers: Vec<LockTier>,
+    /// reward boosts applied once enough NFTs are staked together
+    pub quantity_tiers: Vec<QuantityTier>,
+    /// where claimed rewards are paid from
+    pub reward_source: RewardSource,
+    pub reward_treasury_bump: u8,
+    /// rewards settled into stake accounts that have not been claimed yet
+    pub total_outstanding_rewards: u64,
+    /// the most reward tokens that will ever be paid out, unlimited when unset
+    pub max_total_emission: Option<u64>,
+    /// the unix timestamp after which no more rewards accrue, never ends when unset
+    pub emission_end_ts: Option<i64>,
+    /// the reward tokens paid out so far
+    pub total_emitted: u64,
+    /// how staked NFTs are held by the program
+    pub stake_mode: StakeMode,
+    /// the verified Metaplex collection required for the NFTs being staked,
+    /// replaces the collection name check when set
+    pub collection_mint: Option<Pubkey>,
+    /// the merkle root of the whitelisted mints, replaces `whitelist_addresses` when set
+    pub whitelist_root: Option<[u8; 32]>,
+    /// the merkle root of the `(mint, weight_bps)` rarity weights, all NFTs weigh 1x when unset
+    pub rarity_root: Option<[u8; 32]>,
+    /// reward boosts applied to NFTs whose metadata matches their rule
+    pub trait_boosts: Vec<TraitBoost>,
+    /// the authority proposed by the current one, which takes over once it accepts
+    pub pending_authority: Option<Pubkey>,
+    /// a key besides the authority that can pause the rewarder
+    pub guardian: Option<Pubkey>,
+    /// what is currently halted on the rewarder
+    pub paused: PauseFlags,
+    /// what it costs to unstake an NFT before its locking period has passed
+    pub early_unstake_policy: EarlyUnstakePolicy,
+    /// the seconds between requesting to unstake an NFT and getting it back,
+    /// NFTs are unstaked at once when zero
+    pub unbonding_period: i64,
+    /// the seconds rewards have accrued for, excluding pauses and the time past `emission_end_ts`
+    pub accrual_clock: i64,
+    /// the unix timestamp `accrual_clock` was last advanced at
+    pub accrual_clock_updated_at: i64,
+    /// the summed `reward_weight` of every stake account
+    pub total_reward_weight: u128,
+    /// the summed `reward_weight` of every stake account times its `reward_weight_clock`
+    pub total_reward_weight_clock: u128,
+    /// the staked mints removed from `whitelist_addresses`, with the span of `accrual_clock` they did not accrue for
+    pub whitelist_removals: Vec<WhitelistRemoval>,
 }
 
+impl NftStakeRewarder {
+    /// whether `nft_mint` may be staked, proven against `whitelist_root` when it is set
+    pub fn is_whitelisted(&self, nft_mint: &Pubkey, whitelist_proof: &[[u8; 32]]) -> bool {
+        match &self.whitelist_root {
+            Some(root) => merkle::verify(whitelist_proof, root, merkle::leaf_hash(&[nft_mint.as_ref()])),
+            None => self.whitelist_addresses.contains(nft_mint),
+        }
+    }
+
+    /// whether a staked nft earns rewards, mints removed from `whitelist_addresses` stop accruing
+    /// while NFTs proven against `whitelist_root` accrue until they are unstaked
+    pub fn is_accruing(&self, nft_mint: &Pubkey) -> bool {
+        self.whitelist_root.is_some() || self.whitelist_addresses.contains(nft_mint)
+    }
+
+    /// whether a removal of `nft_mint` from `whitelist_addresses` is recorded
+    pub fn is_removal_recorded(&self, nft_mint: &Pubkey) -> bool {
+        self.whitelist_removals.iter().any(|removal| removal.nft_mint == *nft_mint)
+    }
+
+    /// the seconds of `accrual_clock` between `from` and `to` a staked nft has earned rewards for,
+    /// removed mints skip the time they were not whitelisted and mints that were never whitelisted earn nothing
+    pub fn accrual_time(&self, nft_mint: &Pubkey, from: i64, to: i64) -> i64 {
+        let elapsed = to.saturating_sub(from).max(0);
+        if self.whitelist_root.is_some() {
+            return elapsed;
+        }
+        if !self.is_accruing(nft_mint) && !self.is_removal_recorded(nft_mint) {
+            return 0;
+        }
+        self.whitelist_removals
+            .iter()
+            .filter(|removal| removal.nft_mint == *nft_mint)
+            .map(|removal| {
+                let readded_clock = removal.readded_clock.unwrap_or(to).min(to);
+                readded_clock.saturating_sub(removal.removed_clock.max(from)).max(0)
+            })
+            .fold(elapsed, |elapsed, removed| elapsed.saturating_sub(removed))
+    }
+
+    /// adds the mints that are not whitelisted yet to `whitelist_addresses`, staked mints that were
+    /// removed accrue again from the `accrual_clock` at `current_time`
+    pub fn add_whitelist_addresses(&mut self, mints: &[Pubkey], current_time: i64) {
+        let accrual_clock = self.accrual_clock_at(current_time);
+        for mint in mints {
+            if !self.whitelist_addresses.contains(mint) {
+                self.whitelist_addresses.push(*mint);
+            }
+        }
+        for removal in self.whitelist_removals.iter_mut() {
+            if removal.readded_clock.is_none() && mints.contains(&removal.nft_mint) {
+                removal.readded_clock = Some(accrual_clock);
+            }
+        }
+        self.total_whitelist_address = self.whitelist_addresses.len() as u64;
+    }
+
+    /// removes the mints from `whitelist_addresses`, recording the `accrual_clock` the `staked_mints`
+    /// among them stop accruing at. Mints that are not staked have nothing to record.
+    pub fn remove_whitelist_addresses(
+        &mut self,
+        mints: &[Pubkey],
+        staked_mints: &[Pubkey],
+        current_time: i64,
+    ) -> std::result::Result<(), StakingError> {
+        let accrual_clock = self.accrual_clock_at(current_time);
+        let removals: Vec<WhitelistRemoval> = mints
+            .iter()
+            .filter(|mint| self.whitelist_addresses.contains(mint) && staked_mints.contains(mint))
+            .map(|mint| WhitelistRemoval {
+                nft_mint: *mint,
+                removed_clock: accrual_clock,
+                readded_clock: None,
+            })
+            .collect();
+        if self.whitelist_removals.len() + removals.len() > MAX_WHITELIST_REMOVALS {
+            return Err(StakingError::TooManyWhitelistRemovals);
+        }
+        self.whitelist_removals.extend(removals);
+        self.whitelist_addresses.retain(|address| !mints.contains(address));
+        self.total_whitelist_address = self.whitelist_addresses.len() as u64;
+        Ok(())
+    }
+
+    /// drops the removals of `nft_mint` that ended before `accrual_clock`, or all of them once it is unstaked
+    pub fn prune_whitelist_removals(&mut self, nft_mint: &Pubkey, accrual_clock: Option<i64>) {
+        self.whitelist_removals.retain(|removal| {
+            if removal.nft_mint != *nft_mint {
+                return true;
+            }
+            match (accrual_clock, removal.readded_clock) {
+                (Some(accrual_clock), Some(readded_clock)) => readded_clock > accrual_clock,
+                (Some(_), None) => true,
+                (None, _) => false,
+            }
+        });
+    }
+
+    /// the rarity weight of `nft_mint`, proven against `rarity_root` when it is set
+    pub fn rarity_weight_bps(
+        &self,
+        nft_mint: &Pubkey,
+        rarity: Option<&RarityWeight>,
+    ) -> std::result::Result<u64, StakingError> {
+        let root = match &self.rarity_root {
+            Some(root) => root,
+            None => return Ok(BPS_DENOMINATOR),
+        };
+        let rarity = rarity.ok_or(StakingError::InvalidRarityProof)?;
+        let leaf = merkle::leaf_hash(&[nft_mint.as_ref(), &rarity.weight_bps.to_le_bytes()]);
+        if !merkle::verify(&rarity.proof, root, leaf) {
+            return Err(StakingError::InvalidRarityProof);
+        }
+        Ok(rarity.weight_bps as u64)
+    }
+
+    /// the multiplier of the highest trait boost matched by the metadata `data`, 1x if none matches
+    pub fn trait_multiplier_bps(&self, data: &Data) -> u64 {
+        self.trait_boosts
+            .iter()
+            .filter(|boost| boost.rule.matches(data))
+            .map(|boost| boost.multiplier_bps as u64)
+            .max()
+            .unwrap_or(BPS_DENOMINATOR)
+    }
+
+    /// the weight recorded for an NFT when it is staked, combining its rarity weight with the
+    /// trait boost of its metadata when the metadata is enforced
+    pub fn weight_bps(
+        &self,
+        nft_mint: &Pubkey,
+        rarity: Option<&RarityWeight>,
+        data: Option<&Data>,
+    ) -> std::result::Result<u64, StakingError> {
+        let rarity_weight_bps = self.rarity_weight_bps(nft_mint, rarity)?;
+        let trait_multiplier_bps = data.map_or(BPS_DENOMINATOR, |data| self.trait_multiplier_bps(data));
+        let weight_bps = rarity_weight_bps as u128 * trait_multiplier_bps as u128 / BPS_DENOMINATOR as u128;
+        u64::try_from(weight_bps).map_err(|_| StakingError::MathOverflow)
+    }
+
+    /// replaces the pause flags, advancing `accrual_clock` up to the change first
+    pub fn set_paused(&mut self, paused: PauseFlags, current_time: i64) {
+        self.advance_accrual_clock(current_time);
+        self.paused = paused;
+    }
+
+    /// the `accrual_clock` at `current_time`, it stands still while accrual is paused and past `emission_end_ts`
+    pub fn accrual_clock_at(&self, current_time: i64) -> i64 {
+        if self.paused.accrual {
+            return self.accrual_clock;
+        }
+        let accrued_until = self
+            .emission_end_ts
+            .map_or(current_time, |end_ts| current_time.min(end_ts));
+        self.accrual_clock
+            .saturating_add(accrued_until.saturating_sub(self.accrual_clock_updated_at).max(0))
+    }
+
+    /// moves `accrual_clock` up to `current_time`, required before changing the pause flags or the emission end
+    pub fn advance_accrual_clock(&mut self, current_time: i64) {
+        self.accrual_clock = self.accrual_clock_at(current_time);
+        self.accrual_clock_updated_at = self.accrual_clock_updated_at.max(current_time);
+    }
+
+    /// an upper bound of the rewards accrued by staked NFTs up to `current_time` that have not been settled
+    /// into their stake accounts yet, at the current reward rate and the highest quantity tier
+    pub fn unsettled_rewards(&self, current_time: i64) -> u64 {
+        let max_quantity_multiplier_bps = self
+            .quantity_tiers
+            .iter()
+            .map(|tier| tier.multiplier_bps as u64)
+            .fold(BPS_DENOMINATOR, u64::max);
+        let denominator = 24 * 3600 * BPS_DENOMINATOR as u128 * BPS_DENOMINATOR as u128;
+        (self.accrual_clock_at(current_time) as u128)
+            .checked_mul(self.total_reward_weight)
+            .map(|weighted_time| weighted_time.saturating_sub(self.total_reward_weight_clock))
+            .and_then(|weighted_time| weighted_time.checked_mul(self.reward_rate as u128))
+            .and_then(|reward| reward.checked_mul(max_quantity_multiplier_bps as u128))
+            .and_then(|reward| reward.checked_add(denominator - 1))
+            .and_then(|reward| u64::try_from(reward / denominator).ok())
+            .unwrap_or(u64::MAX)
+    }
+
+    /// whether `locking_period` is offered by one of the lock tiers
+    pub fn is_allowed_locking_period(&self, locking_period: i64) -> bool {
+        self.lock_tiers
+            .iter()
+            .any(|tier| tier.locking_period == locking_period)
+    }
+
+    /// the reward tokens that can still be paid out before reaching `max_total_emission`
+    pub fn remaining_emission(&self) -> Option<u64> {
+        self.max_total_emission
+            .map(|max_total_emission| max_total_emission.saturating_sub(self.total_emitted))
+    }
+
+    /// the multiplier for the lock tier matching `locking_period`, 1x if none matches
+    pub fn lock_multiplier_bps(&self, locking_period: i64) -> u64 {
+        self.lock_tiers
+            .iter()
+            .find(|tier| tier.locking_period == locking_period)
+            .map_or(BPS_DENOMINATOR, |tier| tier.multiplier_bps as u64)
+    }
+
+    /// the multiplier for the highest quantity tier reached by `num_staked`, 1x if none is reached
+    pub fn quantity_multiplier_bps(&self, num_staked: u16) -> u64 {
+        self.quantity_tiers
+            .iter()
+            .filter(|tier| num_staked >= tier.min_count)
+            .max_by_key(|tier| tier.min_count)
+            .map_or(BPS_DENOMINATOR, |tier| tier.multiplier_bps as u64)
+    }
+
+    /// the creator hash Bubblegum stores in the leaf of a compressed NFT with the required creators
+    pub fn creator_hash(&self) -> [u8; 32] {
+        let creator_data: Vec<Vec<u8>> = self
+            .creators
+            .iter()
+            .map(|creator| {
+                [
+                    creator.address.as_ref(),
+                    &[creator.verified as u8],
+                    &[creator.share],
+                ]
+                .concat()
+            })
+            .collect();
+        let creator_slices: Vec<&[u8]> = creator_data.iter().map(|data| data.as_slice()).collect();
+        keccak::hashv(&creator_slices).to_bytes()
+    }
+}
+
+#[derive(Debug, AnchorDeserialize, AnchorSerialize, Default, Clone, Copy, PartialEq, Eq)]
+pub enum RewardSource {
+    /// rewards are minted by the reward authority PDA
+    #[default]
+    Mint,
+    /// rewards are transferred out of the reward treasury, for reward tokens with a fixed supply
+    Treasury,
+}
+
+#[derive(Debug, AnchorDeserialize, AnchorSerialize, Default, Clone, Copy, PartialEq, Eq)]
+pub enum StakeMode {
+    /// the owner of the NFT token account is changed to the stake account
+    #[default]
+    SetAuthority,
+    /// the NFT is transferred into a token account owned by the stake account
+    Escrow,
+    /// the NFT stays in the owner's wallet, delegated to the stake account and frozen
+    Freeze,
+}
+
+/// What happens when an NFT is unstaked before its locking period has passed
+#[derive(Debug, AnchorDeserialize, AnchorSerialize, Default, Clone, Copy, PartialEq, Eq)]
+pub enum EarlyUnstakePolicy {
+    /// the NFT cannot be unstaked until its locking period has passed
+    #[default]
+    HardLock,
+    /// the share of the rewards accrued by the NFT during its lock that its lock tier added is forfeited
+    ForfeitLockBoost,
+    /// a share of the rewards accrued by the NFT during its lock is forfeited, in basis points
+    Slash { slash_bps: u16 },
+    /// the owner pays a fixed amount of reward tokens
+    Fee { amount: u64 },
+}
+
+impl EarlyUnstakePolicy {
+    /// the rewards `nft_staked` accrued during its lock, claimed or not, that are forfeited by unstaking it early
+    pub fn forfeited_rewards(&self, nft_staked: &NftStaked) -> std::result::Result<u64, StakingError> {
+        match *self {
+            EarlyUnstakePolicy::HardLock => Err(StakingError::NFTAUnlocked),
+            EarlyUnstakePolicy::ForfeitLockBoost => Ok(nft_staked.lock_boost_accrued_in_lock),
+            EarlyUnstakePolicy::Slash { slash_bps } => {
+                Ok((nft_staked.accrued_in_lock as u128 * slash_bps as u128 / BPS_DENOMINATOR as u128) as u64)
+            }
+            EarlyUnstakePolicy::Fee { .. } => Ok(0),
+        }
+    }
+
+    /// the reward tokens the owner pays for unstaking early
+    pub fn fee(&self) -> u64 {
+        match *self {
+            EarlyUnstakePolicy::Fee { amount } => amount,
+            _ => 0,
+        }
+    }
+}
+
+#[derive(Debug, AnchorDeserialize, AnchorSerialize, Default, Clone)]
+pub struct LockTier {
+    /// the locking period in seconds that earns this boost
+    pub locking_period: i64,
+    pub multiplier_bps: u32,
+}
+
+#[derive(Debug, AnchorDeserialize, AnchorSerialize, Default, Clone)]
+pub struct QuantityTier {
+    /// the number of NFTs that must be staked together to earn this boost
+    pub min_count: u16,
+    pub multiplier_bps: u32,
+}
+
+#[derive(Debug, AnchorDeserialize, AnchorSerialize, Default, Clone, PartialEq)]
+pub struct WhitelistRemoval {
+    pub nft_mint: Pubkey,
+    /// the `accrual_clock` of the rewarder when the mint was removed
+    pub removed_clock: i64,
+    /// the `accrual_clock` of the rewarder when the mint was whitelisted again, still removed when unset
+    pub readded_clock: Option<i64>,
+}
+
+/// The operations halted while a rewarder is paused, unstaking is always available
+#[derive(Debug, AnchorDeserialize, AnchorSerialize, Default, Clone, Copy, PartialEq)]
+pub struct PauseFlags {
+    pub stake: bool,
+    pub claim: bool,
+    pub accrual: bool,
+}
+
+impl PauseFlags {
+    /// whether every operation halted by `self` is also halted by `other`
+    pub fn is_subset_of(&self, other: &PauseFlags) -> bool {
+        (!self.stake || other.stake) && (!self.claim || other.claim) && (!self.accrual || other.accrual)
+    }
+}
+
+/// A field of the metadata an NFT must match to earn a trait boost
+#[derive(Debug, AnchorDeserialize, AnchorSerialize, Clone, PartialEq)]
+pub enum TraitRule {
+    Symbol { symbol: String },
+    SellerFeeBasisPoints { seller_fee_basis_points: u16 },
+    NameSuffix { suffix: String },
+}
+
+impl TraitRule {
+    pub fn matches(&self, data: &Data) -> bool {
+        // token metadata pads the strings with null characters
+        match self {
+            TraitRule::Symbol { symbol } => data.symbol.trim_end_matches('\0') == symbol,
+            TraitRule::SellerFeeBasisPoints { seller_fee_basis_points } => {
+                data.seller_fee_basis_points == *seller_fee_basis_points
+            }
+            TraitRule::NameSuffix { suffix } => data.name.trim_end_matches('\0').ends_with(suffix.as_str()),
+        }
+    }
+}
+
+#[derive(Debug, AnchorDeserialize, AnchorSerialize, Clone)]
+pub struct TraitBoost {
+    pub rule: TraitRule,
+    pub multiplier_bps: u32,
+}
 
 #[derive(Debug, AnchorDeserialize, AnchorSerialize, Default, Clone)]
 pub struct CreatorStruct {
@@ -55,35 +472,109 @@ impl PartialEq<Creator> for &CreatorStruct {
     }
 }
 
+/// The rarity weight of an NFT with the proof of its leaf in `rarity_root`
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct RarityWeight {
+    pub weight_bps: u32,
+    pub proof: Vec<[u8; 32]>,
+}
+
 #[account]
 pub struct VaultAccount {
     pub total_staked: u32,
     pub reward_mint: Pubkey,
-    pub nft_items_staked: Vec<NftItem>,
     pub bump: u8,
 }
 
+/// Created for every staked NFT and closed again when it is unstaked
+#[account]
+pub struct StakeReceipt {
+    pub owner: Pubkey,
+    pub rewarder: Pubkey,
+    pub nft_mint: Pubkey,
+    pub start_staking: i64,
+    pub locking_period: i64,
+    pub bump: u8,
+    /// the rarity weight and trait boost of the NFT at the time it was staked
+    pub weight_bps: u64,
+}
+
+impl StakeReceipt {
+    /// whether the NFT is still within the locking period it was staked with
+    pub fn is_locked(&self, current_time: i64) -> bool {
+        current_time <= self.start_staking.saturating_add(self.locking_period)
+    }
+}
+
 #[account]
 pub struct NftStakeAccount {
     pub owner: Pubkey,
     pub rewarder: Pubkey,
+    /// every NFT currently staked by the owner
     pub nfts_staked: Vec<NftStaked>,
     pub bump: u8,
     pub last_claimed: i64,
+    /// rewards settled for the owner that have not been claimed yet
     pub claimed_reward: u64,
+    /// the summed lock and rarity weight of the accruing NFTs at the last settlement
+    pub reward_weight: u128,
+    /// the `accrual_clock` of the rewarder at the last settlement
+    pub reward_weight_clock: i64,
 }
 
 #[derive(Debug, AnchorDeserialize, AnchorSerialize, Default, Clone)]
 pub struct NftStaked {
+    pub nft_mint: Pubkey,
     pub locking_period: i64,
-    pub num_staked: u16,
+    /// the lock tier multiplier at the time the NFT was staked
+    pub multiplier_bps: u64,
+    /// the rarity weight and trait boost at the time the NFT was staked
+    pub weight_bps: u64,
+    /// the unix timestamp rewards for this NFT have been accrued up to
+    pub last_accrued: i64,
+    /// the `accrual_clock` of the rewarder at `last_accrued`
+    pub accrual_clock: i64,
+    /// the rewards accrued by this NFT since the last claim
+    pub accrued: u64,
+    /// the rewards accrued by this NFT since its lock started, claimed or not
+    pub accrued_in_lock: u64,
+    /// the part of `accrued_in_lock` its lock tier multiplier added
+    pub lock_boost_accrued_in_lock: u64,
+    /// when unstaking was requested, the unix timestamp the NFT can be unstaked at, it accrues nothing meanwhile
+    pub unbonding_until: Option<i64>,
 }
 
+/// The layout of `VaultAccount` before stake receipts, only read to migrate the accounts created with it
 #[derive(Debug, AnchorDeserialize, AnchorSerialize, Default, Clone)]
-pub struct NftItem {
+pub struct LegacyVaultAccount {
+    pub total_staked: u32,
+    pub reward_mint: Pubkey,
+    pub nft_items_staked: Vec<LegacyNftItem>,
+    pub bump: u8,
+}
+
+#[derive(Debug, AnchorDeserialize, AnchorSerialize, Default, Clone)]
+pub struct LegacyNftItem {
     pub owner: Pubkey,
     pub locking_period: i64,
     pub start_staking: i64,
     pub nft_mint: Pubkey,
     pub flag: bool,
-}
\ No newline at end of file
+}
+
+/// The layout of `NftStakeAccount` before stake receipts, only read to migrate the accounts created with it
+#[derive(Debug, AnchorDeserialize, AnchorSerialize, Default, Clone)]
+pub struct LegacyNftStakeAccount {
+    pub owner: Pubkey,
+    pub rewarder: Pubkey,
+    pub nfts_staked: Vec<LegacyNftStaked>,
+    pub bump: u8,
+    pub last_claimed: i64,
+    pub claimed_reward: u64,
+}
+
+#[derive(Debug, AnchorDeserialize, AnchorSerialize, Default, Clone)]
+pub struct LegacyNftStaked {
+    pub locking_period: i64,
+    pub num_staked: u16,
+}
@@ -0,0 +1,328 @@
+use std::ops::Deref;
+
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::instruction::{AccountMeta, Instruction};
+use anchor_lang::solana_program::program::invoke_signed;
+use anchor_lang::solana_program::program_pack::Pack;
+use anchor_spl::associated_token;
+use spl_token::instruction as token_instruction;
+
+use crate::errors::StakingError;
+
+pub mod spl_token_2022 {
+    use anchor_lang::prelude::*;
+
+    declare_id!("TokenzQdBNbLqP5VEhdkAS6EPFLC1PHnBqCXEpPxuEb");
+}
+
+/// Token-2022 extension types, numbered as in the `ExtensionType` enum of spl-token-2022
+pub mod extension {
+    pub const TRANSFER_FEE_CONFIG: u16 = 1;
+    pub const TRANSFER_FEE_AMOUNT: u16 = 2;
+    pub const DEFAULT_ACCOUNT_STATE: u16 = 6;
+    pub const NON_TRANSFERABLE: u16 = 9;
+    pub const PERMANENT_DELEGATE: u16 = 12;
+    pub const NON_TRANSFERABLE_ACCOUNT: u16 = 13;
+    pub const TRANSFER_HOOK: u16 = 14;
+    pub const TRANSFER_HOOK_ACCOUNT: u16 = 15;
+    pub const PAUSABLE: u16 = 26;
+    pub const PAUSABLE_ACCOUNT: u16 = 27;
+}
+
+// Token-2022 accounts with extensions are padded to the length of a token account,
+// followed by the account type and the extensions
+const ACCOUNT_TYPE_OFFSET: usize = spl_token::state::Account::LEN;
+const EXTENSIONS_OFFSET: usize = ACCOUNT_TYPE_OFFSET + 1;
+const EXTENSION_HEADER_LEN: usize = 4;
+const ACCOUNT_TYPE_MINT: u8 = 1;
+const ACCOUNT_TYPE_ACCOUNT: u8 = 2;
+
+/// whether `key` is the legacy token program or Token-2022
+pub fn is_token_program(key: &Pubkey) -> bool {
+    *key == spl_token::ID || *key == spl_token_2022::ID
+}
+
+/// The address of the associated token account of `wallet` under the given token program
+pub fn get_associated_token_address(wallet: &Pubkey, mint: &Pubkey, token_program: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(
+        &[wallet.as_ref(), token_program.as_ref(), mint.as_ref()],
+        &associated_token::ID,
+    )
+    .0
+}
+
+/// A mint of either token program with the types of its extensions
+#[derive(Clone)]
+pub struct Mint {
+    mint: spl_token::state::Mint,
+    pub extensions: Vec<u16>,
+}
+
+impl Mint {
+    pub fn has_extension(&self, extension_type: u16) -> bool {
+        self.extensions.contains(&extension_type)
+    }
+
+    /// the length of a token account for this mint, including the extensions it requires
+    pub fn token_account_len(&self) -> usize {
+        let required_extensions_len: usize = self
+            .extensions
+            .iter()
+            .filter_map(|extension_type| match *extension_type {
+                extension::TRANSFER_FEE_CONFIG => Some(extension::TRANSFER_FEE_AMOUNT),
+                extension::NON_TRANSFERABLE => Some(extension::NON_TRANSFERABLE_ACCOUNT),
+                extension::TRANSFER_HOOK => Some(extension::TRANSFER_HOOK_ACCOUNT),
+                extension::PAUSABLE => Some(extension::PAUSABLE_ACCOUNT),
+                _ => None,
+            })
+            .map(|account_extension| {
+                EXTENSION_HEADER_LEN
+                    + match account_extension {
+                        extension::TRANSFER_FEE_AMOUNT => 8,
+                        extension::TRANSFER_HOOK_ACCOUNT => 1,
+                        _ => 0,
+                    }
+            })
+            .sum();
+
+        if required_extensions_len == 0 {
+            spl_token::state::Account::LEN
+        } else {
+            EXTENSIONS_OFFSET + required_extensions_len
+        }
+    }
+}
+
+impl Deref for Mint {
+    type Target = spl_token::state::Mint;
+
+    fn deref(&self) -> &Self::Target {
+        &self.mint
+    }
+}
+
+/// A token account of either token program with the types of its extensions
+#[derive(Clone)]
+pub struct TokenAccount {
+    account: spl_token::state::Account,
+    pub extensions: Vec<u16>,
+}
+
+impl Deref for TokenAccount {
+    type Target = spl_token::state::Account;
+
+    fn deref(&self) -> &Self::Target {
+        &self.account
+    }
+}
+
+/// Unpacks a mint owned by `token_program`
+pub fn load_mint(info: &AccountInfo, token_program: &Pubkey) -> std::result::Result<Mint, StakingError> {
+    if info.owner != token_program || !is_token_program(token_program) {
+        return Err(StakingError::InvalidTokenProgram);
+    }
+    let data = info.try_borrow_data().or(Err(StakingError::InvalidTokenAccountData))?;
+    let mint = unpack_base::<spl_token::state::Mint>(&data)?;
+    let extensions = unpack_extensions(&data, ACCOUNT_TYPE_MINT)?;
+    Ok(Mint { mint, extensions })
+}
+
+/// Unpacks a token account owned by `token_program`
+pub fn load_token_account(
+    info: &AccountInfo,
+    token_program: &Pubkey,
+) -> std::result::Result<TokenAccount, StakingError> {
+    if info.owner != token_program || !is_token_program(token_program) {
+        return Err(StakingError::InvalidTokenProgram);
+    }
+    let data = info.try_borrow_data().or(Err(StakingError::InvalidTokenAccountData))?;
+    let account = unpack_base::<spl_token::state::Account>(&data)?;
+    let extensions = unpack_extensions(&data, ACCOUNT_TYPE_ACCOUNT)?;
+    Ok(TokenAccount { account, extensions })
+}
+
+fn unpack_base<T: Pack + anchor_lang::solana_program::program_pack::IsInitialized>(
+    data: &[u8],
+) -> std::result::Result<T, StakingError> {
+    if data.len() < T::LEN {
+        return Err(StakingError::InvalidTokenAccountData);
+    }
+    let state = T::unpack_from_slice(&data[..T::LEN]).or(Err(StakingError::InvalidTokenAccountData))?;
+    if !state.is_initialized() {
+        return Err(StakingError::InvalidTokenAccountData);
+    }
+    Ok(state)
+}
+
+fn unpack_extensions(data: &[u8], account_type: u8) -> std::result::Result<Vec<u16>, StakingError> {
+    let mut extensions = Vec::new();
+    if data.len() <= ACCOUNT_TYPE_OFFSET {
+        return Ok(extensions);
+    }
+    if data[ACCOUNT_TYPE_OFFSET] != account_type {
+        return Err(StakingError::InvalidTokenAccountData);
+    }
+
+    let mut offset = EXTENSIONS_OFFSET;
+    while offset + EXTENSION_HEADER_LEN <= data.len() {
+        let extension_type = u16::from_le_bytes([data[offset], data[offset + 1]]);
+        let length = u16::from_le_bytes([data[offset + 2], data[offset + 3]]) as usize;
+        // the rest of the account is unused
+        if extension_type == 0 {
+            break;
+        }
+        extensions.push(extension_type);
+        offset += EXTENSION_HEADER_LEN + length;
+    }
+
+    Ok(extensions)
+}
+
+fn invoke_token_instruction<'info, T: ToAccountInfos<'info> + ToAccountMetas>(
+    mut ix: Instruction,
+    ctx: &CpiContext<'_, '_, '_, 'info, T>,
+) -> Result<()> {
+    // the spl_token builders only accept the legacy program id, the instruction data is the same for Token-2022
+    ix.program_id = *ctx.program.key;
+    invoke_signed(&ix, &ctx.to_account_infos(), ctx.signer_seeds).map_err(Into::into)
+}
+
+pub fn transfer_checked<'a, 'b, 'c, 'info>(
+    ctx: CpiContext<'a, 'b, 'c, 'info, TransferChecked<'info>>,
+    amount: u64,
+    decimals: u8,
+) -> Result<()> {
+    let ix = token_instruction::transfer_checked(
+        &spl_token::ID,
+        ctx.accounts.from.key,
+        ctx.accounts.mint.key,
+        ctx.accounts.to.key,
+        ctx.accounts.authority.key,
+        &[],
+        amount,
+        decimals,
+    )?;
+    invoke_token_instruction(ix, &ctx)
+}
+
+pub fn mint_to<'a, 'b, 'c, 'info>(
+    ctx: CpiContext<'a, 'b, 'c, 'info, MintTo<'info>>,
+    amount: u64,
+) -> Result<()> {
+    let ix = token_instruction::mint_to(
+        &spl_token::ID,
+        ctx.accounts.mint.key,
+        ctx.accounts.to.key,
+        ctx.accounts.authority.key,
+        &[],
+        amount,
+    )?;
+    invoke_token_instruction(ix, &ctx)
+}
+
+pub fn burn<'a, 'b, 'c, 'info>(
+    ctx: CpiContext<'a, 'b, 'c, 'info, Burn<'info>>,
+    amount: u64,
+) -> Result<()> {
+    let ix = token_instruction::burn(
+        &spl_token::ID,
+        ctx.accounts.from.key,
+        ctx.accounts.mint.key,
+        ctx.accounts.authority.key,
+        &[],
+        amount,
+    )?;
+    invoke_token_instruction(ix, &ctx)
+}
+
+pub fn close_account<'a, 'b, 'c, 'info>(
+    ctx: CpiContext<'a, 'b, 'c, 'info, CloseAccount<'info>>,
+) -> Result<()> {
+    let ix = token_instruction::close_account(
+        &spl_token::ID,
+        ctx.accounts.account.key,
+        ctx.accounts.destination.key,
+        ctx.accounts.authority.key,
+        &[],
+    )?;
+    invoke_token_instruction(ix, &ctx)
+}
+
+pub fn initialize_account3<'a, 'b, 'c, 'info>(
+    ctx: CpiContext<'a, 'b, 'c, 'info, InitializeAccount3<'info>>,
+    owner: &Pubkey,
+) -> Result<()> {
+    let ix = token_instruction::initialize_account3(
+        &spl_token::ID,
+        ctx.accounts.account.key,
+        ctx.accounts.mint.key,
+        owner,
+    )?;
+    invoke_token_instruction(ix, &ctx)
+}
+
+/// Creates an associated token account under the token program of the context accounts, succeeding
+/// when it already exists so it cannot be blocked by someone creating it first
+pub fn create_associated_token_account_idempotent<'a, 'b, 'c, 'info>(
+    ctx: CpiContext<'a, 'b, 'c, 'info, CreateAssociatedTokenAccount<'info>>,
+) -> Result<()> {
+    let ix = Instruction {
+        program_id: associated_token::ID,
+        accounts: vec![
+            AccountMeta::new(*ctx.accounts.payer.key, true),
+            AccountMeta::new(*ctx.accounts.associated_token.key, false),
+            AccountMeta::new_readonly(*ctx.accounts.authority.key, false),
+            AccountMeta::new_readonly(*ctx.accounts.mint.key, false),
+            AccountMeta::new_readonly(*ctx.accounts.system_program.key, false),
+            AccountMeta::new_readonly(*ctx.accounts.token_program.key, false),
+        ],
+        // AssociatedTokenAccountInstruction::CreateIdempotent
+        data: vec![1],
+    };
+    invoke_signed(&ix, &ctx.to_account_infos(), ctx.signer_seeds).map_err(Into::into)
+}
+
+#[derive(Accounts)]
+pub struct TransferChecked<'info> {
+    pub from: AccountInfo<'info>,
+    pub mint: AccountInfo<'info>,
+    pub to: AccountInfo<'info>,
+    pub authority: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct MintTo<'info> {
+    pub mint: AccountInfo<'info>,
+    pub to: AccountInfo<'info>,
+    pub authority: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct Burn<'info> {
+    pub from: AccountInfo<'info>,
+    pub mint: AccountInfo<'info>,
+    pub authority: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CloseAccount<'info> {
+    pub account: AccountInfo<'info>,
+    pub destination: AccountInfo<'info>,
+    pub authority: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeAccount3<'info> {
+    pub account: AccountInfo<'info>,
+    pub mint: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CreateAssociatedTokenAccount<'info> {
+    pub payer: AccountInfo<'info>,
+    pub associated_token: AccountInfo<'info>,
+    pub authority: AccountInfo<'info>,
+    pub mint: AccountInfo<'info>,
+    pub system_program: AccountInfo<'info>,
+    pub token_program: AccountInfo<'info>,
+}
@@ -154,6 +154,16 @@ describe("sol-nft-staking", () => {
         ],
         solNftStakingProgram.programId
       );
+    let [rewardTreasury] =
+      await anchor.web3.PublicKey.findProgramAddress(
+        [
+          Buffer.from(collectionName),
+          solNftStakingProgram.programId.toBuffer(),
+          Buffer.from("reward_treasury"),
+          rewarder.toBuffer(),
+        ],
+        solNftStakingProgram.programId
+      );
     let [stakeAccount, stakeAccountBump] =
       await anchor.web3.PublicKey.findProgramAddress(
         [
@@ -179,10 +189,35 @@ describe("sol-nft-staking", () => {
 
     const rewardRate = 3600 * 24;
     const lockingPeriod = 0;
+    const lockTiers = [
+      { lockingPeriod: new anchor.BN(0), multiplierBps: 10000 },
+      { lockingPeriod: new anchor.BN(7 * 24 * 3600), multiplierBps: 12500 },
+      { lockingPeriod: new anchor.BN(30 * 24 * 3600), multiplierBps: 15000 },
+      { lockingPeriod: new anchor.BN(60 * 24 * 3600), multiplierBps: 17500 },
+      { lockingPeriod: new anchor.BN(90 * 24 * 3600), multiplierBps: 20000 },
+    ];
+    const quantityTiers = [
+      { minCount: 5, multiplierBps: 12500 },
+      { minCount: 10, multiplierBps: 15000 },
+      { minCount: 15, multiplierBps: 17500 },
+      { minCount: 20, multiplierBps: 20000 },
+    ];
     let rewardMint = null;
     let rewardTokenAccount = null;
     let nftMint = null;
     let nftTokenAccount = null;
+    let stakeReceipt = null;
+
+    // the escrow token account of the stake account for an NFT
+    const nftEscrowAddress = (mint: anchor.web3.PublicKey) =>
+      splToken.Token.getAssociatedTokenAddress(
+        splToken.ASSOCIATED_TOKEN_PROGRAM_ID,
+        splToken.TOKEN_PROGRAM_ID,
+        mint,
+        stakeAccount,
+        true
+      );
+    const tokenMetadataProgram = programs.metadata.MetadataProgram.PUBKEY;
 
     before(async () => {
       console.log("airdropping 1 sol to owner");
@@ -215,6 +250,16 @@ describe("sol-nft-staking", () => {
         creator
       );
 
+      [stakeReceipt] = await anchor.web3.PublicKey.findProgramAddress(
+        [
+          Buffer.from(collectionName),
+          solNftStakingProgram.programId.toBuffer(),
+          Buffer.from("stake_receipt"),
+          rewarder.toBuffer(),
+          nftMint.publicKey.toBuffer(),
+        ],
+        solNftStakingProgram.programId
+      );
     });
 
     it("initializes a rewarder", async () => {
@@ -230,12 +275,18 @@ describe("sol-nft-staking", () => {
         creators,
         creator.publicKey,
         true,
+        lockTiers,
+        quantityTiers,
+        { mint: {} },
+        { setAuthority: {} },
         {
           accounts: {
             rewarder: rewarder,
             authority: owner.publicKey,
             rewardAuthority: rewardAuthority,
             rewardMint: rewardMint.publicKey,
+            rewardTreasury,
+            tokenProgram: splToken.TOKEN_PROGRAM_ID,
             systemProgram,
             rent: rentSysvar,
           },
@@ -244,6 +295,75 @@ describe("sol-nft-staking", () => {
       );
     });
 
+    it("updates the boost tiers", async () => {
+      await solNftStakingProgram.rpc.updateBoostTiers(
+        lockTiers,
+        quantityTiers.slice(0, 2),
+        {
+          accounts: {
+            rewarder: rewarder,
+            authority: owner.publicKey,
+          },
+          signers: [owner],
+        }
+      );
+      const rewarderInfo =
+        await solNftStakingProgram.account.nftStakeRewarder.fetch(rewarder);
+      expect(rewarderInfo.quantityTiers.length).to.equal(2);
+    });
+
+    it("transfers the rewarder authority in two steps", async () => {
+      const newAuthority = anchor.web3.Keypair.generate();
+      const updateAccounts = { rewarder, authority: owner.publicKey };
+      const acceptAccounts = { rewarder, pendingAuthority: newAuthority.publicKey };
+
+      await solNftStakingProgram.rpc.proposeAuthority(newAuthority.publicKey, {
+        accounts: updateAccounts,
+        signers: [owner],
+      });
+      await solNftStakingProgram.rpc.cancelAuthorityProposal({
+        accounts: updateAccounts,
+        signers: [owner],
+      });
+      let rewarderInfo =
+        await solNftStakingProgram.account.nftStakeRewarder.fetch(rewarder);
+      expect(rewarderInfo.pendingAuthority).to.equal(null);
+
+      try {
+        await solNftStakingProgram.rpc.acceptAuthority({
+          accounts: acceptAccounts,
+          signers: [newAuthority],
+        });
+        expect.fail("accepted an authority that was not proposed");
+      } catch (err) {
+        expect(err.toString()).to.contain("NoPendingAuthority");
+      }
+
+      await solNftStakingProgram.rpc.proposeAuthority(newAuthority.publicKey, {
+        accounts: updateAccounts,
+        signers: [owner],
+      });
+      await solNftStakingProgram.rpc.acceptAuthority({
+        accounts: acceptAccounts,
+        signers: [newAuthority],
+      });
+      rewarderInfo =
+        await solNftStakingProgram.account.nftStakeRewarder.fetch(rewarder);
+      expect(rewarderInfo.authority.toBase58()).to.equal(
+        newAuthority.publicKey.toBase58()
+      );
+
+      // hand the rewarder back for the rest of the tests
+      await solNftStakingProgram.rpc.proposeAuthority(owner.publicKey, {
+        accounts: { rewarder, authority: newAuthority.publicKey },
+        signers: [newAuthority],
+      });
+      await solNftStakingProgram.rpc.acceptAuthority({
+        accounts: { rewarder, pendingAuthority: owner.publicKey },
+        signers: [owner],
+      });
+    });
+
     it("initializes a valut", async () => {
       await solNftStakingProgram.rpc.initializeValut(
         vaultAccountBump,
@@ -275,8 +395,7 @@ describe("sol-nft-staking", () => {
     it("add the nft to the whitelist address", async () =>{
       console.log("nft address ", nftMint.publicKey.toBase58());
       // console.log(new PublicKey(nftMint.publicKey.toBase58()));
-      await solNftStakingProgram.rpc.updateRewardRate(
-        new anchor.BN(rewardRate),
+      await solNftStakingProgram.rpc.addWhitelistMints(
         [new PublicKey(nftMint.publicKey.toBase58())],
         {
          accounts: {
@@ -302,18 +421,23 @@ describe("sol-nft-staking", () => {
       const nftMetadata = await Metadata.getPDA(nftMint.publicKey);
       await solNftStakingProgram.rpc.stakeNft(
         new anchor.BN(lockingPeriod),
+        [],
+        null,
         {
           accounts: {
             owner: owner.publicKey,
             rewarder,
             rewardAuthority,
             stakeAccount,
-            rewardMint: rewardMint.publicKey,
-            rewardTokenAccount,
             vaultAccount: vaultAccount,
+            stakeReceipt,
             nftMint: nftMint.publicKey,
+            nftEscrow: await nftEscrowAddress(nftMint.publicKey),
+            nftEdition: await programs.metadata.MasterEdition.getPDA(nftMint.publicKey),
             nftTokenAccount,
             tokenProgram: splToken.TOKEN_PROGRAM_ID,
+            tokenMetadataProgram,
+            associatedTokenProgram: splToken.ASSOCIATED_TOKEN_PROGRAM_ID,
             systemProgram,
             rent: rentSysvar,
             clock: clockSysvar,
@@ -330,15 +454,14 @@ describe("sol-nft-staking", () => {
       const vaultAccountData = await solNftStakingProgram.account.vaultAccount.fetch(vaultAccount);
       console.log("the number of total staked nft", vaultAccountData.totalStaked);
       console.log("the address of reard token", vaultAccountData.rewardMint.toBase58());
-      
-      console.log("the nft items staked: [");
-      if(Array.isArray(vaultAccountData.nftItemsStaked)) {
-        vaultAccountData.nftItemsStaked.forEach(element => {
-          console.log("{owner:", element.owner.toBase58());
-          console.log("nftMint:", element.nftMint.toBase58(), "}");
-        });
-      }
-      console.log("]");
+
+      const stakeReceiptData = await solNftStakingProgram.account.stakeReceipt.fetch(stakeReceipt);
+      expect(stakeReceiptData.owner.toBase58()).to.equal(owner.publicKey.toBase58());
+      expect(stakeReceiptData.nftMint.toBase58()).to.equal(nftMint.publicKey.toBase58());
+
+      const stakeAccountData = await solNftStakingProgram.account.nftStakeAccount.fetch(stakeAccount);
+      expect(stakeAccountData.nftsStaked.length).to.equal(1);
+      expect(stakeAccountData.nftsStaked[0].nftMint.toBase58()).to.equal(nftMint.publicKey.toBase58());
 
     });
 
@@ -358,6 +481,7 @@ describe("sol-nft-staking", () => {
           stakeAccount,
           rewardMint: rewardMint.publicKey,
           rewardAccount: rewardTokenAccount,
+          rewardTreasury,
           tokenProgram: splToken.TOKEN_PROGRAM_ID,
           clock: clockSysvar,
         },
@@ -377,24 +501,152 @@ describe("sol-nft-staking", () => {
       //sleep one more second to check that we claim pending rewards on unstake
       await sleep(provider.connection, 2);
 
-      await solNftStakingProgram.rpc.unstakeNft(new anchor.BN(lockingPeriod),{
+      await solNftStakingProgram.rpc.unstakeNft({
         accounts: {
           owner: owner.publicKey,
           rewarder,
-          rewardAuthority,
           stakeAccount,
-          rewardMint: rewardMint.publicKey,
-          rewardTokenAccount,
           nftMint: nftMint.publicKey,
           nftTokenAccount,
+          nftEscrow: await nftEscrowAddress(nftMint.publicKey),
+          nftEdition: await programs.metadata.MasterEdition.getPDA(nftMint.publicKey),
           vaultAccount: vaultAccount,
+          stakeReceipt,
           tokenProgram: splToken.TOKEN_PROGRAM_ID,
+          tokenMetadataProgram,
+          rewardMint: rewardMint.publicKey,
+          rewardAccount: rewardTokenAccount,
+          rewardTreasury,
+          rewardTokenProgram: splToken.TOKEN_PROGRAM_ID,
           clock: clockSysvar,
         },
         signers: [owner],
       });
       let nftAccount = await nftMint.getAccountInfo(nftTokenAccount);
       expect(nftAccount.owner.toBase58()).to.equal(owner.publicKey.toBase58());
+      expect(await provider.connection.getAccountInfo(stakeReceipt)).to.be.null;
+    });
+
+    it("refuses to unstake an NFT before its lock ends, even in an emergency", async () => {
+      const [lockedNftMint, lockedNftTokenAccount] = await mintNFT(
+        provider.connection,
+        owner,
+        creator
+      );
+      const [lockedStakeReceipt] =
+        await anchor.web3.PublicKey.findProgramAddress(
+          [
+            Buffer.from(collectionName),
+            solNftStakingProgram.programId.toBuffer(),
+            Buffer.from("stake_receipt"),
+            rewarder.toBuffer(),
+            lockedNftMint.publicKey.toBuffer(),
+          ],
+          solNftStakingProgram.programId
+        );
+
+      await solNftStakingProgram.rpc.addWhitelistMints(
+        [lockedNftMint.publicKey],
+        {
+          accounts: {
+            rewarder: rewarder,
+            authority: owner.publicKey,
+          },
+          signers: [owner],
+        }
+      );
+
+      const nftMetadata = await Metadata.getPDA(lockedNftMint.publicKey);
+      await solNftStakingProgram.rpc.stakeNft(
+        new anchor.BN(7 * 24 * 3600),
+        [],
+        null,
+        {
+          accounts: {
+            owner: owner.publicKey,
+            rewarder,
+            rewardAuthority,
+            stakeAccount,
+            vaultAccount: vaultAccount,
+            stakeReceipt: lockedStakeReceipt,
+            nftMint: lockedNftMint.publicKey,
+            nftEscrow: await nftEscrowAddress(lockedNftMint.publicKey),
+            nftEdition: await programs.metadata.MasterEdition.getPDA(lockedNftMint.publicKey),
+            nftTokenAccount: lockedNftTokenAccount,
+            tokenProgram: splToken.TOKEN_PROGRAM_ID,
+            tokenMetadataProgram,
+            associatedTokenProgram: splToken.ASSOCIATED_TOKEN_PROGRAM_ID,
+            systemProgram,
+            rent: rentSysvar,
+            clock: clockSysvar,
+          },
+          remainingAccounts: [
+            { pubkey: nftMetadata, isSigner: false, isWritable: false },
+          ],
+          signers: [owner],
+        }
+      );
+
+      // unstaking used to skip the lock check when passed a locking period of 0,
+      // the lock recorded at stake time now always applies
+      try {
+        await solNftStakingProgram.rpc.unstakeNft({
+          accounts: {
+            owner: owner.publicKey,
+            rewarder,
+            stakeAccount,
+            nftMint: lockedNftMint.publicKey,
+            nftTokenAccount: lockedNftTokenAccount,
+            nftEscrow: await nftEscrowAddress(lockedNftMint.publicKey),
+            nftEdition: await programs.metadata.MasterEdition.getPDA(lockedNftMint.publicKey),
+            vaultAccount: vaultAccount,
+            stakeReceipt: lockedStakeReceipt,
+            tokenProgram: splToken.TOKEN_PROGRAM_ID,
+            tokenMetadataProgram,
+            rewardMint: rewardMint.publicKey,
+            rewardAccount: rewardTokenAccount,
+            rewardTreasury,
+            rewardTokenProgram: splToken.TOKEN_PROGRAM_ID,
+            clock: clockSysvar,
+          },
+          signers: [owner],
+        });
+        expect.fail("unstaking a locked NFT should fail");
+      } catch (err) {
+        expect(err.error.errorCode.code).to.equal("NFTAUnlocked");
+      }
+
+      let nftAccount = await lockedNftMint.getAccountInfo(lockedNftTokenAccount);
+      expect(nftAccount.owner.toBase58()).to.equal(stakeAccount.toBase58());
+      let stakeAccountData = await solNftStakingProgram.account.nftStakeAccount.fetch(stakeAccount);
+      expect(stakeAccountData.nftsStaked.length).to.equal(1);
+
+      // the emergency unstake skips the rewards but not the lock, so claiming first is no way out
+      try {
+        await solNftStakingProgram.rpc.emergencyUnstake({
+          accounts: {
+            owner: owner.publicKey,
+            rewarder,
+            stakeAccount,
+            nftMint: lockedNftMint.publicKey,
+            nftTokenAccount: lockedNftTokenAccount,
+            nftEscrow: await nftEscrowAddress(lockedNftMint.publicKey),
+            nftEdition: await programs.metadata.MasterEdition.getPDA(lockedNftMint.publicKey),
+            vaultAccount: vaultAccount,
+            stakeReceipt: lockedStakeReceipt,
+            tokenProgram: splToken.TOKEN_PROGRAM_ID,
+            tokenMetadataProgram,
+            clock: clockSysvar,
+          },
+          signers: [owner],
+        });
+        expect.fail("an emergency unstake of a locked NFT should fail");
+      } catch (err) {
+        expect(err.error.errorCode.code).to.equal("NFTAUnlocked");
+      }
+
+      nftAccount = await lockedNftMint.getAccountInfo(lockedNftTokenAccount);
+      expect(nftAccount.owner.toBase58()).to.equal(stakeAccount.toBase58());
     });
   });
 });